@@ -72,7 +72,7 @@ async fn main() {
         .unwrap();
 
     // Run your executor.
-    let _ = exe.execute().await.unwrap();
+    exe.execute().await.unwrap();
 
     // Collect your results.
     let outputs = exe.get_outputs().to_owned();
@@ -96,7 +96,7 @@ async fn main() {
         .unwrap();
 
     // Run your executor.
-    let _ = exe.execute().await.unwrap();
+    exe.execute().await.unwrap();
 
     // Collect your results.
     let outputs = exe.get_outputs().to_owned();
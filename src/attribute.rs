@@ -0,0 +1,336 @@
+//! ns-3 attribute overrides
+//!
+//! Params return overrides from `BuildCmd::build_attributes`, which the executor passes to the
+//! ns-3 program according to `ExecutorBuilder::attribute_mode`.
+//!
+//! ## Example
+//!
+//! ```
+//! use ns3_parallel::attribute::{AttributeOverride, AttributeValue};
+//! use std::time::Duration;
+//!
+//! let min_rto =
+//!     AttributeOverride::default_value("ns3::TcpSocketBase::MinRto", Duration::from_millis(200))
+//!         .unwrap();
+//! assert_eq!(min_rto.to_arg(), "--ns3::TcpSocketBase::MinRto=200ms");
+//! assert_eq!(min_rto.to_raw_text(), "default ns3::TcpSocketBase::MinRto \"200ms\"");
+//!
+//! let rate = AttributeOverride::default_value(
+//!     "ns3::PointToPointNetDevice::DataRate",
+//!     AttributeValue::DataRate(10_000_000),
+//! )
+//! .unwrap();
+//! assert_eq!(rate.to_arg(), "--ns3::PointToPointNetDevice::DataRate=10Mbps");
+//!
+//! assert!(AttributeOverride::default_value("TcpSocketBase::MinRto", 1u64).is_err());
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Used for ExecutorBuilder.
+///
+/// Specify how the attribute overrides of a param are passed to the ns-3 program. Default to
+/// `AttributeMode::CommandLine`.
+///
+/// - `CommandLine`: appended to the program arguments as `--ns3::Class::Attribute=value` and
+///   `--Global=value`, which `CommandLine::Parse` applies.
+/// - `ConfigStore`: written in RawText format to `ns3-attributes.txt` in the working directory
+///   of the task, which requires `ExecutorBuilder::output_dir`. The program is pointed to the
+///   file through the `ns3::ConfigStore` defaults, and must create a `ConfigStore` and call
+///   `ConfigureDefaults` after parsing its command line.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeMode {
+    CommandLine,
+    ConfigStore,
+}
+
+/// Name of the ConfigStore file written in the working directory of a task.
+pub const CONFIG_STORE_FILE: &str = "ns3-attributes.txt";
+
+/// # AttributePath
+///
+/// Full name of an ns-3 attribute, such as `ns3::TcpSocketBase::MinRto`: the `ns3` namespace,
+/// possibly nested namespaces, the class and the attribute, separated by `::`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AttributePath(String);
+
+impl AttributePath {
+    pub fn new(path: &str) -> Result<Self, Error> {
+        let segments: Vec<&str> = path.split("::").collect();
+        if segments.len() < 3 || segments[0] != "ns3" || !segments.iter().all(|s| is_ident(s)) {
+            return Err(Error::InvalidConfig(format!(
+                "Invalid attribute path: {:?}, expected ns3::Class::Attribute.",
+                path
+            )));
+        }
+        Ok(AttributePath(path.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for AttributePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// # AttributeValue
+///
+/// Value of an attribute, formatted the way ns-3 parses it.
+///
+/// - `Time` is written with the largest unit that represents it exactly, e.g. `200ms`.
+/// - `DataRate` is in bits per second, written with the largest exact unit, e.g. `10Mbps`.
+/// - `QueuePackets` and `QueueBytes` are queue sizes, written as `100p` and `1500B`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Double(f64),
+    String(String),
+    Time(Duration),
+    DataRate(u64),
+    QueuePackets(u64),
+    QueueBytes(u64),
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::Bool(v) => write!(f, "{}", v),
+            AttributeValue::Int(v) => write!(f, "{}", v),
+            AttributeValue::Uint(v) => write!(f, "{}", v),
+            AttributeValue::Double(v) => write!(f, "{}", v),
+            AttributeValue::String(v) => f.write_str(v),
+            AttributeValue::Time(v) => {
+                let nanos = v.as_nanos();
+                let (value, unit) = [(1_000_000_000, "s"), (1_000_000, "ms"), (1_000, "us")]
+                    .into_iter()
+                    .find(|(scale, _)| nanos % scale == 0)
+                    .map(|(scale, unit)| (nanos / scale, unit))
+                    .unwrap_or((nanos, "ns"));
+                write!(f, "{}{}", value, unit)
+            }
+            AttributeValue::DataRate(v) => {
+                let (value, unit) = [
+                    (1_000_000_000, "Gbps"),
+                    (1_000_000, "Mbps"),
+                    (1_000, "kbps"),
+                ]
+                .into_iter()
+                .find(|(scale, _)| *v != 0 && v % scale == 0)
+                .map(|(scale, unit)| (v / scale, unit))
+                .unwrap_or((*v, "bps"));
+                write!(f, "{}{}", value, unit)
+            }
+            AttributeValue::QueuePackets(v) => write!(f, "{}p", v),
+            AttributeValue::QueueBytes(v) => write!(f, "{}B", v),
+        }
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(v: bool) -> Self {
+        AttributeValue::Bool(v)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(v: i64) -> Self {
+        AttributeValue::Int(v)
+    }
+}
+
+impl From<u64> for AttributeValue {
+    fn from(v: u64) -> Self {
+        AttributeValue::Uint(v)
+    }
+}
+
+impl From<f64> for AttributeValue {
+    fn from(v: f64) -> Self {
+        AttributeValue::Double(v)
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(v: &str) -> Self {
+        AttributeValue::String(v.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(v: String) -> Self {
+        AttributeValue::String(v)
+    }
+}
+
+impl From<Duration> for AttributeValue {
+    fn from(v: Duration) -> Self {
+        AttributeValue::Time(v)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    Default(AttributePath),
+    Global(String),
+}
+
+/// # AttributeOverride
+///
+/// A value to set before the simulation starts: either the default value of an attribute,
+/// as `Config::SetDefault` does, or a global value such as `RngRun`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeOverride {
+    target: Target,
+    value: AttributeValue,
+}
+
+impl AttributeOverride {
+    /// Override the default value of the attribute `path`, e.g. `ns3::TcpSocketBase::MinRto`.
+    pub fn default_value(path: &str, value: impl Into<AttributeValue>) -> Result<Self, Error> {
+        Ok(AttributeOverride {
+            target: Target::Default(AttributePath::new(path)?),
+            value: value.into(),
+        })
+    }
+
+    /// Override the global value `name`, e.g. `RngRun` or `SimulatorImplementationType`.
+    pub fn global(name: &str, value: impl Into<AttributeValue>) -> Result<Self, Error> {
+        if !is_ident(name) {
+            return Err(Error::InvalidConfig(format!(
+                "Invalid global value name: {:?}.",
+                name
+            )));
+        }
+        Ok(AttributeOverride {
+            target: Target::Global(name.to_string()),
+            value: value.into(),
+        })
+    }
+
+    /// Name of the overridden attribute or global value.
+    pub fn name(&self) -> &str {
+        match &self.target {
+            Target::Default(path) => path.as_str(),
+            Target::Global(name) => name,
+        }
+    }
+
+    pub fn value(&self) -> &AttributeValue {
+        &self.value
+    }
+
+    /// Command line argument of the override, e.g. `--ns3::TcpSocketBase::MinRto=200ms`.
+    pub fn to_arg(&self) -> String {
+        format!("--{}={}", self.name(), self.value)
+    }
+
+    /// Line of the override in a ConfigStore RawText file.
+    pub fn to_raw_text(&self) -> String {
+        let kind = match self.target {
+            Target::Default(_) => "default",
+            Target::Global(_) => "global",
+        };
+        format!("{} {} \"{}\"", kind, self.name(), self.value)
+    }
+}
+
+/// Content of a ConfigStore RawText file holding `overrides`.
+pub(crate) fn raw_text(overrides: &[AttributeOverride]) -> String {
+    overrides.iter().map(|o| o.to_raw_text() + "\n").collect()
+}
+
+/// Arguments loading the ConfigStore file `path` as input.
+pub(crate) fn config_store_args(path: &std::path::Path) -> Vec<String> {
+    vec![
+        format!("--ns3::ConfigStore::Filename={}", path.display()),
+        "--ns3::ConfigStore::Mode=Load".to_string(),
+        "--ns3::ConfigStore::FileFormat=RawText".to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_paths_are_validated() {
+        for path in [
+            "ns3::TcpSocketBase::MinRto",
+            "ns3::lte::LteEnbRrc::SrsPeriodicity",
+            "ns3::_Private::Attribute_2",
+        ] {
+            assert_eq!(AttributePath::new(path).unwrap().as_str(), path);
+        }
+        for path in [
+            "",
+            "TcpSocketBase::MinRto",
+            "ns3::MinRto",
+            "std::TcpSocketBase::MinRto",
+            "ns3::::MinRto",
+            "ns3::TcpSocketBase::",
+            "ns3::TcpSocketBase::2MinRto",
+            "ns3::TcpSocketBase::Min Rto",
+            "ns3::TcpSocketBase:MinRto",
+        ] {
+            assert!(
+                matches!(AttributePath::new(path), Err(Error::InvalidConfig(_))),
+                "{:?}",
+                path
+            );
+        }
+        assert!(AttributeOverride::global("RngRun", 1u64).is_ok());
+        assert!(AttributeOverride::global("ns3::RngRun", 1u64).is_err());
+        assert!(AttributeOverride::global("", 1u64).is_err());
+    }
+
+    #[test]
+    fn values_are_written_with_the_largest_exact_unit() {
+        let cases = [
+            (AttributeValue::Time(Duration::from_secs(2)), "2s"),
+            (AttributeValue::Time(Duration::from_micros(1500)), "1500us"),
+            (AttributeValue::Time(Duration::from_nanos(1)), "1ns"),
+            (AttributeValue::Time(Duration::ZERO), "0s"),
+            (AttributeValue::DataRate(2_500_000), "2500kbps"),
+            (AttributeValue::DataRate(1_000_000_000), "1Gbps"),
+            (AttributeValue::DataRate(0), "0bps"),
+            (AttributeValue::QueuePackets(100), "100p"),
+            (AttributeValue::QueueBytes(1500), "1500B"),
+            (AttributeValue::Bool(true), "true"),
+        ];
+        for (value, text) in cases {
+            assert_eq!(value.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn config_store_raw_text() {
+        let overrides = [
+            AttributeOverride::default_value("ns3::TcpL4Protocol::SocketType", "ns3::TcpBbr")
+                .unwrap(),
+            AttributeOverride::global("RngRun", 3u64).unwrap(),
+        ];
+        assert_eq!(
+            raw_text(&overrides),
+            "default ns3::TcpL4Protocol::SocketType \"ns3::TcpBbr\"\nglobal RngRun \"3\"\n"
+        );
+        assert_eq!(overrides[1].to_arg(), "--RngRun=3");
+    }
+}
@@ -0,0 +1,165 @@
+//! Command line tool of ns3-parallel
+//!
+//! ```text
+//! ns3-parallel rerun <MANIFEST> <EXPERIMENT>/<INDEX> [--wrapper <CMD>] [--ns-log <SPEC>] [--dry-run]
+//! ```
+
+use std::process::ExitCode;
+
+use ns3_parallel::rerun::Rerun;
+
+const USAGE: &str = "Usage: ns3-parallel rerun <MANIFEST> <EXPERIMENT>/<INDEX> [OPTIONS]
+
+Run again a task recorded in the manifest of a campaign, with the same command line,
+environment and working directory.
+
+Options:
+  --wrapper <CMD>   Run the program under CMD, e.g. \"gdb --args\" or \"valgrind\"
+  --ns-log <SPEC>   Set NS_LOG, e.g. \"TcpSocketBase=level_all|prefix_time\"
+  --dry-run         Print the command instead of running it
+  -h, --help        Print this help";
+
+#[derive(Debug, PartialEq)]
+struct RerunArgs {
+    manifest: String,
+    experiment: String,
+    index: usize,
+    wrapper: Option<String>,
+    ns_log: Option<String>,
+    dry_run: bool,
+}
+
+fn parse_rerun(args: &[String]) -> Result<RerunArgs, String> {
+    let mut positional = vec![];
+    let mut wrapper = None;
+    let mut ns_log = None;
+    let mut dry_run = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value of {}.", name))
+        };
+        match arg.as_str() {
+            "--wrapper" => wrapper = Some(value("--wrapper")?),
+            "--ns-log" => ns_log = Some(value("--ns-log")?),
+            "--dry-run" => dry_run = true,
+            _ if arg.starts_with("--wrapper=") => wrapper = Some(arg[10..].to_string()),
+            _ if arg.starts_with("--ns-log=") => ns_log = Some(arg[9..].to_string()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}.", arg)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let [manifest, task] = <[String; 2]>::try_from(positional)
+        .map_err(|_| "Expected a manifest and a task.".to_string())?;
+    let (experiment, index) = task
+        .rsplit_once('/')
+        .and_then(|(e, i)| Some((e.to_string(), i.parse().ok()?)))
+        .ok_or_else(|| format!("Task {:?} is not <EXPERIMENT>/<INDEX>.", task))?;
+    Ok(RerunArgs {
+        manifest,
+        experiment,
+        index,
+        wrapper,
+        ns_log,
+        dry_run,
+    })
+}
+
+async fn rerun(args: RerunArgs) -> Result<ExitCode, String> {
+    let mut rerun = Rerun::from_manifest_file(&args.manifest, &args.experiment, args.index)
+        .map_err(|e| format!("{:?}", e))?;
+    if let Some(wrapper) = &args.wrapper {
+        rerun = rerun.wrapper(wrapper);
+    }
+    if let Some(ns_log) = &args.ns_log {
+        rerun = rerun.ns_log(ns_log);
+    }
+    if let Err(e) = rerun.check_ns3() {
+        eprintln!("warning: {:?}", e);
+    }
+    if args.dry_run {
+        println!("cd {}", rerun.command().cwd.display());
+        println!("{}", rerun.command());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let status = rerun.status().await.map_err(|e| format!("{:?}", e))?;
+    Ok(match status.code() {
+        Some(code) => ExitCode::from(code as u8),
+        None => ExitCode::FAILURE,
+    })
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("rerun") => match parse_rerun(&args[1..]) {
+            Ok(args) => rerun(args).await,
+            Err(e) => Err(format!("{}\n\n{}", e, USAGE)),
+        },
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => Err(USAGE.to_string()),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        ExitCode::from(2)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<RerunArgs, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_rerun(&args)
+    }
+
+    #[test]
+    fn rerun_arguments() {
+        let args = parse(&[
+            "out/manifest.json",
+            "exp/3",
+            "--wrapper=gdb --args",
+            "--dry-run",
+        ]);
+        assert_eq!(
+            args.unwrap(),
+            RerunArgs {
+                manifest: "out/manifest.json".to_string(),
+                experiment: "exp".to_string(),
+                index: 3,
+                wrapper: Some("gdb --args".to_string()),
+                ns_log: None,
+                dry_run: true,
+            }
+        );
+        let args = parse(&["m.json", "--ns-log", "Tcp=level_all", "sweeps/tcp/12"]).unwrap();
+        assert_eq!(args.experiment, "sweeps/tcp");
+        assert_eq!(args.index, 12);
+        assert_eq!(args.ns_log.as_deref(), Some("Tcp=level_all"));
+    }
+
+    #[test]
+    fn invalid_rerun_arguments() {
+        let error = |args: &[&str]| parse(args).unwrap_err();
+        assert_eq!(
+            error(&["m.json", "exp/3", "--wrapper"]),
+            "Missing value of --wrapper."
+        );
+        assert_eq!(
+            error(&["m.json", "exp/3", "--gdb"]),
+            "Unknown option --gdb."
+        );
+        assert_eq!(error(&["m.json"]), "Expected a manifest and a task.");
+        assert_eq!(
+            error(&["m.json", "exp/last"]),
+            "Task \"exp/last\" is not <EXPERIMENT>/<INDEX>."
+        );
+    }
+}
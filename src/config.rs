@@ -0,0 +1,766 @@
+//! Config file loading and validation
+
+use indexmap::IndexMap;
+use serde::de::value::StringDeserializer;
+use serde::de::{
+    DeserializeOwned, DeserializeSeed, Deserializer, IgnoredAny, IntoDeserializer, MapAccess,
+    Visitor,
+};
+use serde::forward_to_deserialize_any;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::executor::ConfigFormat;
+use crate::metrics::MetricRule;
+use crate::wrapper::Wrapper;
+
+/// Used for ExecutorBuilder.
+///
+/// Specify how fields that are not part of your config struct are treated. Default to
+/// `Strictness::Warn`.
+///
+/// - `Lenient`: unknown fields are silently ignored.
+/// - `Warn`: unknown fields are reported as warnings, see `Executor::get_config_warnings`.
+/// - `Strict`: unknown fields are reported as errors and the executor is not built.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    Lenient,
+    Warn,
+    Strict,
+}
+
+/// Severity of a `ConfigDiagnostic`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// # ConfigDiagnostic
+///
+/// A problem found in the config file, located by experiment key and by line and column
+/// (both starting from 1) when the format reports them.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    pub severity: Severity,
+    pub file: String,
+    pub experiment: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: ")?,
+            Severity::Error => write!(f, "error: ")?,
+        }
+        write!(f, "{}", self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        if let Some(experiment) = &self.experiment {
+            write!(f, " [{}]", experiment)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Keys of an experiment that are read by the executor itself rather than by the config
+/// struct, so they are never reported as unknown fields. A config struct may not have a field
+/// with one of these names, since it would never be set.
+const RESERVED_KEYS: &[&str] = &["env", "metrics", "wrapper"];
+
+/// Reserved keys of an experiment, see `RESERVED_KEYS`.
+#[derive(Debug, Default, Deserialize)]
+struct Reserved {
+    #[serde(default)]
+    env: IndexMap<String, String>,
+    #[serde(default)]
+    metrics: Vec<MetricRule>,
+    #[serde(default)]
+    wrapper: Option<Wrapper>,
+}
+
+/// Configs loaded from a config file, along with every diagnostic found while loading.
+///
+/// Experiments that fail to deserialize are left out of `configs`, `envs`, `metrics` and
+/// `wrappers`.
+pub(crate) struct LoadedConfigs<T> {
+    pub configs: IndexMap<String, T>,
+    /// The `env` block of each experiment.
+    pub envs: IndexMap<String, Vec<(String, String)>>,
+    /// The `metrics` rules of each experiment.
+    pub metrics: IndexMap<String, Vec<MetricRule>>,
+    /// The `wrapper` of the experiments that set one.
+    pub wrappers: IndexMap<String, Wrapper>,
+    pub diagnostics: Vec<ConfigDiagnostic>,
+}
+
+pub(crate) fn check_config_file(config_path: &str, ext: &ConfigFormat) -> Result<PathBuf, Error> {
+    let config_file_path = match Path::new(&config_path).canonicalize() {
+        Ok(path) => path,
+        Err(e) => {
+            return Err(Error::FileNotFound(format!(
+                "Can not locate config file: {:?}.",
+                e
+            )));
+        }
+    };
+    match config_file_path.extension() {
+        Some(t) => match ext {
+            ConfigFormat::Ron => {
+                if t != "ron" {
+                    return Err(Error::InvalidConfig(
+                        "Config file must be a ron file.".to_string(),
+                    ));
+                }
+            }
+            ConfigFormat::Json => {
+                if t != "json" {
+                    return Err(Error::InvalidConfig(
+                        "Config file must be a json file.".to_string(),
+                    ));
+                }
+            }
+            ConfigFormat::Toml => {
+                if t != "toml" {
+                    return Err(Error::InvalidConfig(
+                        "Config file must be a toml file.".to_string(),
+                    ));
+                }
+            }
+            ConfigFormat::Yaml => {
+                if t != "yaml" {
+                    return Err(Error::InvalidConfig(
+                        "Config file must be a yaml file.".to_string(),
+                    ));
+                }
+            }
+        },
+        None => {
+            return Err(Error::InvalidConfig(
+                "Config file must have a valid file extension.".to_string(),
+            ));
+        }
+    }
+    Ok(config_file_path)
+}
+
+/// Every experiment of the config file as a raw value, reserved keys included.
+pub(crate) fn load_raw_configs(
+    config_file_path: &Path,
+    config_format: &ConfigFormat,
+) -> Result<IndexMap<String, serde_json::Value>, Error> {
+    let source = std::fs::read_to_string(config_file_path)?;
+    deserialize_document(&source, config_format).map_err(|e| {
+        let file = config_file_path.display().to_string();
+        Error::InvalidConfigFormat(e.into_diagnostic(&file, None).to_string())
+    })
+}
+
+/// Load every experiment of the config file.
+///
+/// Each experiment is deserialized on its own, so one broken experiment does not hide the
+/// problems of the others. The reserved keys are taken out of the experiment as it is read, and
+/// the config struct gets the other keys. Only failing to read the file and a config struct
+/// with a field named after a reserved key are returned as an `Err`, everything else ends up in
+/// `LoadedConfigs::diagnostics`.
+pub(crate) fn load_configs<T: DeserializeOwned>(
+    config_file_path: &Path,
+    config_format: &ConfigFormat,
+    strictness: Strictness,
+) -> Result<LoadedConfigs<T>, Error> {
+    if let Some(field) = struct_fields::<T>()
+        .iter()
+        .find(|field| RESERVED_KEYS.contains(field))
+    {
+        return Err(Error::InvalidConfig(format!(
+            "Field `{}` of the config struct clashes with the reserved key `{}` of the executor.",
+            field, field
+        )));
+    }
+    let file = config_file_path.display().to_string();
+    let source = std::fs::read_to_string(config_file_path)?;
+    let mut loaded = LoadedConfigs {
+        configs: IndexMap::new(),
+        envs: IndexMap::new(),
+        metrics: IndexMap::new(),
+        wrappers: IndexMap::new(),
+        diagnostics: vec![],
+    };
+    let keys: IndexMap<String, IgnoredAny> = match deserialize_document(&source, config_format) {
+        Ok(keys) => keys,
+        Err(e) => {
+            loaded.diagnostics.push(e.into_diagnostic(&file, None));
+            return Ok(loaded);
+        }
+    };
+    for key in keys.keys() {
+        let mut unknown = vec![];
+        let mut reserved = Reserved::default();
+        let result =
+            deserialize_experiment::<T>(&source, config_format, key, &mut reserved, |path| {
+                unknown.push(path)
+            });
+        match result {
+            Ok(config) => {
+                // The key was found by the first pass, so it is always there in practice.
+                if let Some(config) = config {
+                    loaded.configs.insert(key.to_owned(), config);
+                    loaded
+                        .envs
+                        .insert(key.to_owned(), reserved.env.into_iter().collect());
+                    loaded.metrics.insert(key.to_owned(), reserved.metrics);
+                    if let Some(wrapper) = reserved.wrapper {
+                        loaded.wrappers.insert(key.to_owned(), wrapper);
+                    }
+                }
+            }
+            Err(e) => loaded
+                .diagnostics
+                .push(e.into_diagnostic(&file, Some(key.to_owned()))),
+        }
+        // Fields unknown to the reserved keys themselves.
+        unknown.retain(|path| {
+            let field = path.split('.').next().unwrap_or_default();
+            !RESERVED_KEYS.contains(&field)
+        });
+        let severity = match strictness {
+            Strictness::Lenient => continue,
+            Strictness::Warn => Severity::Warning,
+            Strictness::Strict => Severity::Error,
+        };
+        for path in unknown {
+            loaded.diagnostics.push(ConfigDiagnostic {
+                severity,
+                file: file.clone(),
+                experiment: Some(key.to_owned()),
+                line: None,
+                column: None,
+                message: format!("unknown field `{}`", path),
+            });
+        }
+    }
+    Ok(loaded)
+}
+
+/// Render diagnostics, one per line, for `Error::InvalidConfigFormat`.
+pub(crate) fn render_diagnostics(diagnostics: &[ConfigDiagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// An error raised by one of the config formats, with its position when available.
+struct FormatError {
+    message: String,
+    position: Option<(usize, usize)>,
+}
+
+impl FormatError {
+    fn into_diagnostic(self, file: &str, experiment: Option<String>) -> ConfigDiagnostic {
+        ConfigDiagnostic {
+            severity: Severity::Error,
+            file: file.to_string(),
+            experiment,
+            line: self.position.map(|p| p.0),
+            column: self.position.map(|p| p.1),
+            message: self.message,
+        }
+    }
+
+    fn from_toml(e: toml::de::Error, source: &str) -> Self {
+        FormatError {
+            message: e.message().trim().to_string(),
+            position: e.span().map(|span| line_column(source, span.start)),
+        }
+    }
+
+    fn from_json(e: serde_json::Error) -> Self {
+        let position = match e.line() {
+            0 => None,
+            line => Some((line, e.column())),
+        };
+        FormatError {
+            message: strip_location(&e.to_string()),
+            position,
+        }
+    }
+
+    fn from_yaml(e: serde_yaml::Error, source: &str) -> Self {
+        FormatError {
+            position: e.location().map(|l| line_column(source, l.index())),
+            message: strip_location(&e.to_string()),
+        }
+    }
+
+    fn from_ron(e: ron::error::SpannedError) -> Self {
+        FormatError {
+            message: e.code.to_string(),
+            position: Some((e.position.line, e.position.col)),
+        }
+    }
+}
+
+/// serde_json and serde_yaml append the position to their messages, which is
+/// reported separately here.
+fn strip_location(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message.to_string(),
+    }
+}
+
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+fn deserialize_document<T: DeserializeOwned>(
+    source: &str,
+    config_format: &ConfigFormat,
+) -> Result<T, FormatError> {
+    match config_format {
+        ConfigFormat::Ron => ron::from_str(source).map_err(FormatError::from_ron),
+        ConfigFormat::Json => serde_json::from_str(source).map_err(FormatError::from_json),
+        ConfigFormat::Yaml => {
+            serde_yaml::from_str(source).map_err(|e| FormatError::from_yaml(e, source))
+        }
+        ConfigFormat::Toml => toml::from_str(source).map_err(|e| FormatError::from_toml(e, source)),
+    }
+}
+
+/// Deserialize the experiment `key` out of the whole document, so that errors keep their
+/// position in the file, its reserved keys going to `reserved`. `ignored` is called with the
+/// path of every field inside the experiment that `T` does not know about.
+fn deserialize_experiment<T: DeserializeOwned>(
+    source: &str,
+    config_format: &ConfigFormat,
+    key: &str,
+    reserved: &mut Reserved,
+    mut ignored: impl FnMut(String),
+) -> Result<Option<T>, FormatError> {
+    let mut callback = |path: serde_ignored::Path| {
+        let mut segments = vec![];
+        collect_segments(&path, &mut segments);
+        if segments.first().map(String::as_str) == Some(key) && segments.len() > 1 {
+            ignored(segments[1..].join("."));
+        }
+    };
+    let visitor = ExperimentVisitor::<T> {
+        key,
+        reserved,
+        marker: PhantomData,
+    };
+    match config_format {
+        ConfigFormat::Ron => {
+            let mut de = ron::Deserializer::from_str(source).map_err(FormatError::from_ron)?;
+            let result =
+                serde_ignored::Deserializer::new(&mut de, &mut callback).deserialize_map(visitor);
+            result.map_err(|e| FormatError::from_ron(de.span_error(e)))
+        }
+        ConfigFormat::Json => {
+            let mut de = serde_json::Deserializer::from_str(source);
+            serde_ignored::Deserializer::new(&mut de, &mut callback)
+                .deserialize_map(visitor)
+                .map_err(FormatError::from_json)
+        }
+        ConfigFormat::Yaml => {
+            let de = serde_yaml::Deserializer::from_str(source);
+            serde_ignored::Deserializer::new(de, &mut callback)
+                .deserialize_map(visitor)
+                .map_err(|e| FormatError::from_yaml(e, source))
+        }
+        ConfigFormat::Toml => {
+            let de = toml::Deserializer::new(source);
+            serde_ignored::Deserializer::new(de, &mut callback)
+                .deserialize_map(visitor)
+                .map_err(|e| FormatError::from_toml(e, source))
+        }
+    }
+}
+
+fn collect_segments(path: &serde_ignored::Path, segments: &mut Vec<String>) {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => {}
+        Path::Seq { parent, index } => {
+            collect_segments(parent, segments);
+            segments.push(index.to_string());
+        }
+        Path::Map { parent, key } => {
+            collect_segments(parent, segments);
+            segments.push(key.to_owned());
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => collect_segments(parent, segments),
+    }
+}
+
+/// Visit the top level map of a config file and deserialize only the value of `key`.
+struct ExperimentVisitor<'a, T> {
+    key: &'a str,
+    reserved: &'a mut Reserved,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T: DeserializeOwned> Visitor<'de> for ExperimentVisitor<'_, T> {
+    type Value = Option<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of experiments")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut config = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == self.key && config.is_none() {
+                config = Some(map.next_value_seed(ExperimentSeed {
+                    reserved: &mut *self.reserved,
+                    marker: PhantomData::<T>,
+                })?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Deserialize an experiment into `T`, minus its reserved keys which go to `reserved`.
+struct ExperimentSeed<'a, T> {
+    reserved: &'a mut Reserved,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T: DeserializeOwned> DeserializeSeed<'de> for ExperimentSeed<'_, T> {
+    type Value = T;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        T::deserialize(SplitDeserializer {
+            inner: deserializer,
+            reserved: self.reserved,
+        })
+    }
+}
+
+/// Wrap the deserializer of an experiment, so the map read by the config struct skips the
+/// reserved keys. Anything but a map or a struct is read as is.
+struct SplitDeserializer<'a, D> {
+    inner: D,
+    reserved: &'a mut Reserved,
+}
+
+macro_rules! forward_to_inner {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+                self.inner.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for SplitDeserializer<'_, D> {
+    type Error = D::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        let visitor = SplitVisitor {
+            inner: visitor,
+            reserved: self.reserved,
+        };
+        self.inner.deserialize_any(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        let visitor = SplitVisitor {
+            inner: visitor,
+            reserved: self.reserved,
+        };
+        self.inner.deserialize_map(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let visitor = SplitVisitor {
+            inner: visitor,
+            reserved: self.reserved,
+        };
+        self.inner.deserialize_struct(name, fields, visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+
+    forward_to_inner! {
+        deserialize_bool() deserialize_i8() deserialize_i16() deserialize_i32() deserialize_i64()
+        deserialize_i128() deserialize_u8() deserialize_u16() deserialize_u32() deserialize_u64()
+        deserialize_u128() deserialize_f32() deserialize_f64() deserialize_char()
+        deserialize_str() deserialize_string() deserialize_bytes() deserialize_byte_buf()
+        deserialize_option() deserialize_unit() deserialize_seq() deserialize_identifier()
+        deserialize_ignored_any()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+    }
+}
+
+/// Visitor of the config struct, given the map of the experiment without its reserved keys.
+struct SplitVisitor<'a, V> {
+    inner: V,
+    reserved: &'a mut Reserved,
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for SplitVisitor<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(formatter)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_map(SplitMap {
+            map,
+            reserved: self.reserved,
+        })
+    }
+}
+
+struct SplitMap<'a, A> {
+    map: A,
+    reserved: &'a mut Reserved,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for SplitMap<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        while let Some(key) = self.map.next_key::<String>()? {
+            match key.as_str() {
+                "env" => self.reserved.env = self.map.next_value()?,
+                "metrics" => self.reserved.metrics = self.map.next_value()?,
+                "wrapper" => self.reserved.wrapper = self.map.next_value()?,
+                _ => {
+                    let key: StringDeserializer<A::Error> = key.into_deserializer();
+                    return seed.deserialize(key).map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+}
+
+/// Names of the fields of `T`, recorded by a deserializer that fails right after `T` asks for
+/// a struct. Empty when `T` is not a struct.
+fn struct_fields<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(serde::de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(serde::de::Error::custom("fields recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    #[serde(default)]
+    struct TestConfig {
+        app_name: String,
+        sim_time: u32,
+    }
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    struct EnvConfig {
+        env: IndexMap<String, String>,
+    }
+
+    fn try_load<T: DeserializeOwned>(
+        name: &str,
+        format: ConfigFormat,
+        source: &str,
+        strictness: Strictness,
+    ) -> Result<LoadedConfigs<T>, Error> {
+        let path =
+            std::env::temp_dir().join(format!("ns3-parallel-{}-{}", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let loaded = load_configs::<T>(&path, &format, strictness);
+        let _ = std::fs::remove_file(&path);
+        loaded
+    }
+
+    fn load<T: DeserializeOwned>(
+        name: &str,
+        format: ConfigFormat,
+        source: &str,
+        strictness: Strictness,
+    ) -> LoadedConfigs<T> {
+        try_load(name, format, source, strictness).unwrap()
+    }
+
+    #[test]
+    fn reserved_keys_are_split_from_the_config_in_every_format() {
+        let sources = [
+            (
+                ConfigFormat::Toml,
+                "[exp]\napp_name = \"a\"\nenv = { NS_LOG = \"x\" }\nwrapper = \"gdb\"\nsim_time = 2\n",
+            ),
+            (
+                ConfigFormat::Json,
+                r#"{"exp": {"app_name": "a", "env": {"NS_LOG": "x"}, "wrapper": "gdb", "sim_time": 2}}"#,
+            ),
+            (
+                ConfigFormat::Yaml,
+                "exp:\n  app_name: a\n  env:\n    NS_LOG: x\n  wrapper: gdb\n  sim_time: 2\n",
+            ),
+            (
+                ConfigFormat::Ron,
+                r#"{"exp": (app_name: "a", env: {"NS_LOG": "x"}, wrapper: Some(gdb), sim_time: 2)}"#,
+            ),
+        ];
+        for (format, source) in sources {
+            let loaded = load::<TestConfig>("split", format, source, Strictness::Strict);
+            assert_eq!(loaded.diagnostics, vec![], "{}", source);
+            assert_eq!(
+                loaded.configs["exp"],
+                TestConfig {
+                    app_name: "a".to_string(),
+                    sim_time: 2,
+                }
+            );
+            assert_eq!(
+                loaded.envs["exp"],
+                vec![("NS_LOG".to_string(), "x".to_string())]
+            );
+            assert_eq!(loaded.wrappers["exp"], Wrapper::Gdb);
+            assert!(loaded.metrics["exp"].is_empty());
+        }
+    }
+
+    #[test]
+    fn invalid_reserved_keys_are_located() {
+        let source = "[exp]\napp_name = \"a\"\nwrapper = 3\n";
+        let loaded =
+            load::<TestConfig>("bad-wrapper", ConfigFormat::Toml, source, Strictness::Warn);
+        assert!(loaded.configs.is_empty());
+        assert_eq!(loaded.diagnostics.len(), 1);
+        assert_eq!(loaded.diagnostics[0].severity, Severity::Error);
+        assert_eq!(loaded.diagnostics[0].experiment.as_deref(), Some("exp"));
+        assert_eq!(loaded.diagnostics[0].line, Some(3));
+    }
+
+    #[test]
+    fn config_fields_named_after_reserved_keys_are_rejected() {
+        let source = "[exp]\nenv = { NS_LOG = \"x\" }\n";
+        for strictness in [Strictness::Lenient, Strictness::Strict] {
+            let loaded =
+                try_load::<EnvConfig>("reserved-field", ConfigFormat::Toml, source, strictness);
+            assert!(matches!(loaded, Err(Error::InvalidConfig(m)) if m.contains("`env`")));
+        }
+    }
+
+    #[test]
+    fn unknown_fields_follow_the_strictness() {
+        let source = "[exp]\napp_name = \"a\"\nsim_tme = 2\n";
+        let loaded = load::<TestConfig>("lenient", ConfigFormat::Toml, source, Strictness::Lenient);
+        assert_eq!(loaded.diagnostics, vec![]);
+        assert_eq!(loaded.configs["exp"].app_name, "a");
+        for (strictness, severity) in [
+            (Strictness::Warn, Severity::Warning),
+            (Strictness::Strict, Severity::Error),
+        ] {
+            let loaded = load::<TestConfig>("unknown", ConfigFormat::Toml, source, strictness);
+            assert_eq!(loaded.diagnostics.len(), 1);
+            assert_eq!(loaded.diagnostics[0].severity, severity);
+            assert_eq!(loaded.diagnostics[0].experiment.as_deref(), Some("exp"));
+            assert_eq!(loaded.diagnostics[0].message, "unknown field `sim_tme`");
+        }
+    }
+
+    #[test]
+    fn a_broken_experiment_does_not_hide_the_others() {
+        let source = "[a]\nsim_time = \"x\"\n\n[b]\nsim_time = 2\n\n[c]\nsim_time = -1\n";
+        let loaded = load::<TestConfig>("broken", ConfigFormat::Toml, source, Strictness::Warn);
+        assert_eq!(loaded.configs.keys().collect::<Vec<_>>(), vec!["b"]);
+        let located: Vec<_> = loaded
+            .diagnostics
+            .iter()
+            .map(|d| (d.severity, d.experiment.as_deref(), d.line))
+            .collect();
+        assert_eq!(
+            located,
+            vec![
+                (Severity::Error, Some("a"), Some(2)),
+                (Severity::Error, Some("c"), Some(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_documents_are_a_single_diagnostic() {
+        let loaded = load::<TestConfig>(
+            "invalid",
+            ConfigFormat::Json,
+            "{\"exp\": {",
+            Strictness::Warn,
+        );
+        assert!(loaded.configs.is_empty());
+        assert_eq!(loaded.diagnostics.len(), 1);
+        assert_eq!(loaded.diagnostics[0].experiment, None);
+        assert_eq!(loaded.diagnostics[0].line, Some(1));
+        assert!(loaded.diagnostics[0].to_string().starts_with("error: "));
+    }
+}
@@ -1,5 +1,9 @@
 //! Core traits for the library
 
+use crate::attribute::AttributeOverride;
+use crate::limits::ResourceLimits;
+use crate::schedule::ResourceEstimate;
+
 /// # BuildParam
 ///
 /// This trait is used to build the parameters for the NS3 program.
@@ -124,4 +128,36 @@ pub trait BuildParam<P: BuildCmd> {
 /// }
 pub trait BuildCmd {
     fn build_cmd(&self) -> String;
+
+    /// Environment variables to set for the task of this param, such as `NS_LOG` or
+    /// `NS_GLOBAL_VALUE`. Default to none.
+    ///
+    /// They are applied on top of the `env` block of the experiment in the config file, and
+    /// win over it when both set the same variable.
+    fn build_env(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+
+    /// ns-3 attribute and global value overrides of this param. Default to none.
+    ///
+    /// They are passed to the program as set by `ExecutorBuilder::attribute_mode`, see
+    /// `AttributeOverride`.
+    fn build_attributes(&self) -> Vec<AttributeOverride> {
+        vec![]
+    }
+
+    /// Resource limits of the task of this param. Default to none.
+    ///
+    /// The limits set here win over the ones of `ExecutorBuilder::limits`, see
+    /// `ResourceLimits`.
+    fn build_limits(&self) -> ResourceLimits {
+        ResourceLimits::default()
+    }
+
+    /// Memory and CPU slots the task of this param needs, which the executor admits tasks
+    /// by, and how long it runs. Default to one CPU slot, unknown memory and unknown runtime,
+    /// see `ResourceEstimate`.
+    fn build_resources(&self) -> ResourceEstimate {
+        ResourceEstimate::default()
+    }
 }
@@ -0,0 +1,228 @@
+//! Crashes of ns-3 programs
+//!
+//! A `Crash` is detected from the exit status of the task, which tells the signal and whether a
+//! core was dumped. waf however runs the ns-3 program as its own child and exits normally when
+//! the program is killed by a signal, only printing `Command [...] terminated with signal
+//! SIGSEGV.` on stderr, so that message is looked for when the exit status holds no signal.
+//!
+//! With `ExecutorBuilder::core_dumps`, the core file size limit of the tasks is raised to its
+//! hard limit, and the core file of a crashed attempt is moved to `core.<attempt>` in the
+//! directory of the task. With `ExecutorBuilder::crash_backtrace` as well, a backtrace of every
+//! thread is taken from the core with `gdb -batch` and saved next to it as
+//! `backtrace.<attempt>.txt`. A task failing because of a crash fails with `Error::Crashed`,
+//! whose message holds the signal, the core file and the backtrace.
+//!
+//! Core files are looked for where `/proc/sys/kernel/core_pattern` puts them: in the working
+//! directory of the program for a relative pattern such as the default `core`, or in the
+//! directory of an absolute pattern. Patterns piping the core to a program, such as
+//! systemd-coredump, leave nothing to collect.
+
+use regex::Regex;
+use std::fmt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::time::SystemTime;
+
+use crate::limits::set_soft_limit;
+
+/// # Crash
+///
+/// An attempt of a task whose program was killed by a signal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crash {
+    /// Name of the signal, e.g. `SIGSEGV`.
+    pub signal: String,
+    /// Path of the program, when waf reported it.
+    pub program: Option<PathBuf>,
+    /// Whether the exit status says a core was dumped. `None` when the signal was only reported
+    /// by waf.
+    pub core_dumped: Option<bool>,
+    /// Core file, once collected in the directory of the task.
+    pub core: Option<PathBuf>,
+    /// Backtrace of every thread, taken from the core.
+    pub backtrace: Option<String>,
+}
+
+impl Crash {
+    /// The crash of the program whose output is `output`, if it crashed.
+    pub fn detect(output: &Output) -> Option<Crash> {
+        if let Some(signal) = output.status.signal() {
+            return Some(Crash {
+                signal: signal_name(signal),
+                program: None,
+                core_dumped: Some(output.status.core_dumped()),
+                core: None,
+                backtrace: None,
+            });
+        }
+        if output.status.success() {
+            return None;
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let line = stderr
+            .lines()
+            .rev()
+            .find(|l| l.contains("terminated with signal"))?;
+        let signal = line
+            .split("terminated with signal ")
+            .nth(1)?
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .next()?
+            .to_string();
+        // waf prints the command as a python list: Command ['/path/to/program', ...] ...
+        let program = line
+            .split_once("['")
+            .and_then(|(_, rest)| rest.split_once('\''))
+            .map(|(program, _)| PathBuf::from(program));
+        Some(Crash {
+            signal,
+            program,
+            core_dumped: None,
+            core: None,
+            backtrace: None,
+        })
+    }
+}
+
+impl fmt::Display for Crash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.program {
+            Some(program) => write!(f, "{} killed by {}", program.display(), self.signal)?,
+            None => write!(f, "NS3 program killed by {}", self.signal)?,
+        }
+        if let Some(core) = &self.core {
+            write!(f, ", core dumped to {}", core.display())?;
+        }
+        write!(f, ".")?;
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, "\n{}", backtrace.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGILL => "SIGILL",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return format!("signal {}", signal),
+    };
+    name.to_string()
+}
+
+/// What the executor does when an attempt crashes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CrashPolicy {
+    /// Command template of `ExecutorBuilder::crash_wrapper` for the directory of the task.
+    pub template: Option<String>,
+    /// Directory of the task, where core files are collected, with `core_dumps`.
+    pub core_dir: Option<PathBuf>,
+    pub backtrace: bool,
+}
+
+/// Raise the soft limit on the size of core files to the hard limit, see `set_soft_limit`.
+pub(crate) fn enable_core_dumps() -> std::io::Result<()> {
+    set_soft_limit(libc::RLIMIT_CORE, libc::RLIM_INFINITY)
+}
+
+/// Move the core file written since `since` by a program run in `work_dir` to
+/// `<work_dir>/core.<attempt>`.
+pub(crate) fn collect_core(work_dir: &Path, since: SystemTime, attempt: u32) -> Option<PathBuf> {
+    let pattern = std::fs::read_to_string("/proc/sys/kernel/core_pattern").ok()?;
+    let pattern = pattern.trim();
+    if pattern.starts_with('|') {
+        return None;
+    }
+    let pattern = work_dir.join(pattern);
+    let dir = pattern.parent()?;
+    // Specifiers such as `%p` are only known to the kernel, they match anything, and
+    // `core_uses_pid` may add the pid.
+    let name = pattern.file_name()?.to_string_lossy();
+    let literals: Vec<String> = name.split('%').map(regex::escape).collect();
+    let mut name_regex = literals[0].clone();
+    for literal in &literals[1..] {
+        name_regex.push_str(".*");
+        name_regex.push_str(literal.get(1..).unwrap_or_default());
+    }
+    let name_regex = Regex::new(&format!(r"^{}(\.\d+)?$", name_regex)).ok()?;
+    let core = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| name_regex.is_match(&entry.file_name().to_string_lossy()))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .filter(|(modified, _)| *modified >= since)
+        .max()?
+        .1;
+    let collected = work_dir.join(format!("core.{}", attempt));
+    // A rename fails across file systems, where a copy does not.
+    std::fs::rename(&core, &collected)
+        .or_else(|_| std::fs::copy(&core, &collected).and_then(|_| std::fs::remove_file(&core)))
+        .ok()?;
+    Some(collected)
+}
+
+/// Backtrace of every thread in `core` with `gdb -batch`, saved as
+/// `backtrace.<attempt>.txt` next to it. Symbols are only resolved when the program is known.
+pub(crate) async fn backtrace(program: Option<&Path>, core: &Path, attempt: u32) -> Option<String> {
+    let mut gdb = tokio::process::Command::new("gdb");
+    gdb.args(["-batch", "-ex", "thread apply all bt"]);
+    match program {
+        Some(program) => gdb.arg(program).arg(core),
+        None => gdb.arg("-c").arg(core),
+    };
+    let output = gdb.output().await.ok()?;
+    let backtrace = String::from_utf8_lossy(&output.stdout).into_owned();
+    if let Some(dir) = core.parent() {
+        let _ = std::fs::write(dir.join(format!("backtrace.{}.txt", attempt)), &backtrace);
+    }
+    Some(backtrace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::ExitStatus;
+
+    fn output(status: i32, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(status),
+            stdout: vec![],
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn crash_is_read_from_the_exit_status() {
+        let crash = Crash::detect(&output(libc::SIGSEGV | 0x80, "")).unwrap();
+        assert_eq!(crash.signal, "SIGSEGV");
+        assert_eq!(crash.core_dumped, Some(true));
+        let crash = Crash::detect(&output(libc::SIGXCPU, "")).unwrap();
+        assert_eq!(crash.signal, "SIGXCPU");
+        assert_eq!(crash.core_dumped, Some(false));
+    }
+
+    #[test]
+    fn crash_hidden_by_waf_is_read_from_stderr() {
+        let stderr = "Waf: Leaving directory `/ns-3/build'\n\
+                      Command ['/ns-3/build/scratch/sim', '--run=1'] terminated with signal \
+                      SIGSEGV. Run it under a debugger to get more information.\n";
+        let crash = Crash::detect(&output(1 << 8, stderr)).unwrap();
+        assert_eq!(crash.signal, "SIGSEGV");
+        assert_eq!(
+            crash.program,
+            Some(PathBuf::from("/ns-3/build/scratch/sim"))
+        );
+        assert_eq!(crash.core_dumped, None);
+        assert_eq!(Crash::detect(&output(1 << 8, "assert failed\n")), None);
+        assert_eq!(Crash::detect(&output(0, stderr)), None);
+    }
+}
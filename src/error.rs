@@ -4,6 +4,8 @@ use ron::error::SpannedError;
 use std::io;
 use tokio::task::JoinError;
 
+use crate::plan::TaskCommand;
+
 /// # Error
 ///
 /// Error type for NS3 executor.
@@ -18,6 +20,22 @@ pub enum Error {
     JoinError(String),
     NotImplement(String),
     RetryLimitExceed,
+    ParseFail(String),
+    MissingMetric(String),
+    Crashed(String),
+    LimitExceeded(String),
+}
+
+/// # TaskError
+///
+/// Error of a single task, identified by its experiment and by the position of its param in
+/// the params returned by `BuildParam::build_param`.
+#[derive(Debug, Clone)]
+pub struct TaskError {
+    pub experiment: String,
+    pub index: usize,
+    pub command: TaskCommand,
+    pub error: Error,
 }
 
 impl From<io::Error> for Error {
@@ -38,6 +56,13 @@ impl From<SpannedError> for Error {
     }
 }
 
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::IoError(format!("{:?}", e))
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
         Error::InvalidConfigFormat(format!("{:?}", e))
@@ -49,3 +74,9 @@ impl From<serde_yaml::Error> for Error {
         Error::InvalidConfigFormat(format!("{:?}", e))
     }
 }
+
+impl From<roxmltree::Error> for Error {
+    fn from(e: roxmltree::Error) -> Self {
+        Error::ParseFail(format!("{:?}", e))
+    }
+}
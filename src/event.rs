@@ -0,0 +1,183 @@
+//! Events emitted while executing
+
+use futures::Stream;
+use indexmap::IndexMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::crash::Crash;
+use crate::error::Error;
+use crate::plan::TaskCommand;
+use crate::progress::ProgressReporter;
+
+/// The task an event is about.
+///
+/// `index` is the position of the param in the params returned by `BuildParam::build_param`
+/// for `experiment`, which is also how tasks are identified in `Executor::plan`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub experiment: String,
+    pub index: usize,
+    pub command: TaskCommand,
+    /// Estimated runtime of the task, see `LaunchOrder::LongestFirst`. `None` when no task of
+    /// the campaign has one.
+    pub estimate: Option<Duration>,
+}
+
+/// # Event
+///
+/// Something that happened while `Executor::execute` was running, and when it happened.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub time: SystemTime,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum EventKind {
+    BuildStarted,
+    BuildFinished {
+        success: bool,
+        elapsed: Duration,
+    },
+    /// All params were built, with the number of tasks of each experiment, and the sum of the
+    /// estimated runtimes of these tasks when they have one.
+    CampaignStarted {
+        counts: IndexMap<String, usize>,
+        estimates: IndexMap<String, Duration>,
+    },
+    TaskQueued {
+        task: TaskInfo,
+    },
+    /// An attempt of the task was launched, `attempt` starting from 1.
+    TaskStarted {
+        task: TaskInfo,
+        attempt: u32,
+    },
+    /// An attempt failed and the task is launched again as `attempt`.
+    TaskRetried {
+        task: TaskInfo,
+        attempt: u32,
+        elapsed: Duration,
+    },
+    /// The task succeeded. `attempts` is 0 when its output was taken from the cache set with
+    /// `Executor::resume_from`.
+    TaskSucceeded {
+        task: TaskInfo,
+        attempts: u32,
+        elapsed: Duration,
+    },
+    /// The task failed for good. `attempts` is 0 when it failed before being launched, because
+    /// of the pre-run hook for instance.
+    TaskFailed {
+        task: TaskInfo,
+        attempts: u32,
+        elapsed: Duration,
+        error: Option<Error>,
+    },
+    /// The program of an attempt was killed by a signal, see `Crash`.
+    TaskCrashed {
+        task: TaskInfo,
+        attempt: u32,
+        crash: Crash,
+    },
+    /// An attempt was killed after running longer than the task timeout.
+    TaskTimedOut {
+        task: TaskInfo,
+        attempt: u32,
+        elapsed: Duration,
+    },
+    /// A sink left columns of the record of the task out, see `ResultSink::dropped_columns`.
+    /// Each column is reported once per sink.
+    ColumnsDropped {
+        task: TaskInfo,
+        columns: Vec<String>,
+    },
+    CampaignDone {
+        succeeded: usize,
+        failed: usize,
+        elapsed: Duration,
+    },
+}
+
+/// # EventStream
+///
+/// Receiving end of `Executor::subscribe`, usable as a `futures::Stream` of events or through
+/// `recv`. The stream ends when the executor it was subscribed to is dropped.
+#[derive(Debug)]
+pub struct EventStream {
+    rx: UnboundedReceiver<Event>,
+}
+
+impl EventStream {
+    pub(crate) fn new(rx: UnboundedReceiver<Event>) -> Self {
+        EventStream { rx }
+    }
+
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// Dispatch events to the subscribers and to the progress reporter.
+pub(crate) struct Monitor<'a> {
+    pub progress: &'a dyn ProgressReporter,
+    pub subscribers: &'a [UnboundedSender<Event>],
+}
+
+impl Monitor<'_> {
+    pub fn emit(&self, kind: EventKind) {
+        match &kind {
+            EventKind::BuildStarted => self.progress.build_started(),
+            EventKind::BuildFinished {
+                success: true,
+                elapsed,
+            } => self.progress.build_finished(*elapsed),
+            EventKind::CampaignStarted { counts, estimates } => {
+                self.progress.tasks_planned(counts);
+                if !estimates.is_empty() {
+                    self.progress.work_planned(estimates);
+                }
+            }
+            EventKind::TaskStarted { task, attempt: 1 } => {
+                self.progress.task_started(&task.experiment)
+            }
+            EventKind::TaskSucceeded { task, .. } | EventKind::TaskFailed { task, .. } => {
+                if let Some(estimate) = task.estimate {
+                    self.progress.work_done(&task.experiment, estimate);
+                }
+                let success = matches!(kind, EventKind::TaskSucceeded { .. });
+                self.progress.task_finished(&task.experiment, success)
+            }
+            EventKind::CampaignDone { .. } => self.progress.finished(),
+            _ => {}
+        }
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let event = Event {
+            time: SystemTime::now(),
+            kind,
+        };
+        for tx in self.subscribers {
+            let _ = tx.send(event.clone());
+        }
+    }
+
+    /// Emit one event for each of `tasks`.
+    pub fn emit_each(&self, tasks: &[TaskInfo], kind: impl Fn(TaskInfo) -> EventKind) {
+        for task in tasks {
+            self.emit(kind(task.clone()));
+        }
+    }
+}
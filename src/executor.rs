@@ -1,18 +1,50 @@
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
-use pbr::MultiBar;
+use futures::stream::{self, Stream, StreamExt};
+use indexmap::IndexMap;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::future::Future;
+use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
-use std::process::Output;
-use tokio::process::Command;
-use tokio::task::spawn_blocking;
+use std::pin::pin;
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::time::{timeout, MissedTickBehavior};
 
+use crate::attribute::*;
+use crate::config::*;
 use crate::core::*;
-use crate::error::Error;
+use crate::crash::{backtrace, collect_core, enable_core_dumps, Crash, CrashPolicy};
+use crate::error::{Error, TaskError};
+use crate::event::*;
+use crate::export::{
+    CacheHandle, CachedTask, HistoryHandle, ResultCache, ResultSink, Revision, RuntimeHistory,
+    SinkHandle, TaskRecord,
+};
+use crate::hook::*;
+use crate::limits::ResourceLimits;
+use crate::manifest::*;
+use crate::metrics::{MetricExtractor, MetricRule};
+use crate::plan::*;
+use crate::process::{peak_rss, GroupGuard};
+use crate::progress::*;
+use crate::schedule::{available_memory, schedule, Demand, ResourceEstimate};
+use crate::wrapper::Wrapper;
 
 const DEFAULT_RETRY_LIMIT: i32 = 5;
 
+/// Interval at which the peak RSS of a running task is sampled.
+const PEAK_RSS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Interval at which `ProgressReporter::tick` is called while tasks run.
+const PROGRESS_TICK: Duration = Duration::from_secs(1);
+
 /// Used for ExecutorBuilder.
 ///
 /// Specify the format of your config file. Default to `ConfigFormat::Toml`
@@ -24,35 +56,118 @@ pub enum ConfigFormat {
     Yaml,
 }
 
+/// Used for ExecutorBuilder.
+///
+/// Specify the order in which tasks are launched. Default to `LaunchOrder::Sequential`.
+///
+/// - `Sequential`: experiments in the order they appear in the config file, and params of each
+///   experiment in the order returned by `BuildParam::build_param`.
+/// - `Shuffle(seed)`: the sequential order shuffled with a seeded RNG, which spreads heavy
+///   experiments across the run while staying reproducible for the same seed.
+/// - `LongestFirst`: the sequential order sorted by estimated runtime, longest first, so the
+///   longest tasks do not start last and leave a single core busy at the end of the campaign.
+///   A task is estimated by the `runtime` of `BuildCmd::build_resources`, or else from the
+///   history set with `Executor::estimate_from`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchOrder {
+    Sequential,
+    Shuffle(u64),
+    LongestFirst,
+}
+
 #[derive(Debug, Clone)]
-pub struct Executor<T: Default + BuildParam<P>, P: BuildCmd> {
+pub struct Executor<T: Default + BuildParam<P>, P: BuildCmd, O = ()> {
     config_path: String,
     config_format: ConfigFormat,
     ns3_path: String,
     task_concurrent: usize,
     retry_limit: u32,
-    pub configs: HashMap<String, T>,
-    pub outputs: HashMap<String, Vec<Task<P>>>,
+    launch_order: LaunchOrder,
+    dedup: bool,
+    task_timeout: Option<Duration>,
+    progress: Arc<dyn ProgressReporter>,
+    post_process: PostProcessor<P, O>,
+    keep_raw_output: bool,
+    output_dir: Option<PathBuf>,
+    attribute_mode: AttributeMode,
+    manifest_path: PathBuf,
+    pre_run: Option<PreRunHook<P>>,
+    sinks: Vec<SinkHandle<P>>,
+    cache: Option<CacheHandle>,
+    history: Option<HistoryHandle>,
+    subscribers: Vec<UnboundedSender<Event>>,
+    config_warnings: Vec<ConfigDiagnostic>,
+    config_envs: IndexMap<String, Vec<(String, String)>>,
+    metric_extractors: IndexMap<String, MetricExtractor>,
+    wrappers: IndexMap<String, Wrapper>,
+    crash_wrapper: Option<Wrapper>,
+    core_dumps: bool,
+    crash_backtrace: bool,
+    limits: ResourceLimits,
+    memory_budget: Option<u64>,
+    learn_memory: bool,
+    pub configs: IndexMap<String, T>,
+    pub outputs: IndexMap<String, Vec<Task<P, O>>>,
 }
 
 #[derive(Debug, Clone)]
-pub struct ExecutorBuilder {
+pub struct ExecutorBuilder<H = NoPostProcess> {
     pub config_path: Option<String>,
     pub config_format: Option<ConfigFormat>,
     pub ns3_path: Option<String>,
     pub task_concurrent: Option<usize>,
     pub retry_limit: Option<u32>,
+    pub launch_order: Option<LaunchOrder>,
+    pub strictness: Option<Strictness>,
+    pub dedup: Option<bool>,
+    pub task_timeout: Option<Duration>,
+    pub progress: Option<Arc<dyn ProgressReporter>>,
+    pub post_process: H,
+    pub keep_raw_output: Option<bool>,
+    pub output_dir: Option<String>,
+    pub attribute_mode: Option<AttributeMode>,
+    pub metrics: Option<Vec<MetricRule>>,
+    pub manifest_path: Option<String>,
+    pub wrapper: Option<Wrapper>,
+    pub crash_wrapper: Option<Wrapper>,
+    pub core_dumps: Option<bool>,
+    pub crash_backtrace: Option<bool>,
+    pub limits: Option<ResourceLimits>,
+    pub memory_budget: Option<u64>,
+    pub learn_memory: Option<bool>,
 }
 
 #[derive(Debug, Clone)]
-pub struct Task<P: BuildCmd> {
+pub struct Task<P: BuildCmd, O = ()> {
     pub param: P,
+    /// Position of `param` in the params returned by `BuildParam::build_param`.
+    pub index: usize,
+    /// The command that was run for `param`, environment variables included.
+    pub command: TaskCommand,
+    /// Directory the ns-3 program ran in.
+    pub work_dir: PathBuf,
     pub output: Output,
     pub stdout: String,
     pub stderr: String,
+    /// Whether the output was taken from the cache set with `Executor::resume_from`, in which
+    /// case `attempts` and `elapsed` are the ones of the cached run.
+    pub cached: bool,
+    /// Attempts made to run the command.
+    pub attempts: u32,
+    /// Time spent running the command, retries included.
+    pub elapsed: Duration,
+    /// Metrics extracted from `stdout` and `stderr` by the metric rules of the experiment.
+    pub metrics: IndexMap<String, f64>,
+    /// Reports written in `work_dir` by the wrappers the program ran under, see `Wrapper`.
+    pub reports: Vec<PathBuf>,
+    /// Largest resident set size of the processes of the command, in bytes, sampled while it
+    /// ran. `None` for a cached task.
+    pub peak_rss: Option<u64>,
+    /// Result of `ExecutorBuilder::post_process` for this task.
+    pub processed: O,
 }
 
-impl<T: Default + BuildParam<P>, P: BuildCmd> Executor<T, P> {
+impl<T: Default + BuildParam<P>, P: BuildCmd, O> Executor<T, P, O> {
     pub fn get_config_path(&self) -> &str {
         &self.config_path
     }
@@ -73,120 +188,993 @@ impl<T: Default + BuildParam<P>, P: BuildCmd> Executor<T, P> {
         self.retry_limit
     }
 
-    pub fn get_configs(&self) -> &HashMap<String, T> {
+    pub fn get_launch_order(&self) -> LaunchOrder {
+        self.launch_order
+    }
+
+    pub fn get_dedup(&self) -> bool {
+        self.dedup
+    }
+
+    pub fn get_task_timeout(&self) -> Option<Duration> {
+        self.task_timeout
+    }
+
+    pub fn get_keep_raw_output(&self) -> bool {
+        self.keep_raw_output
+    }
+
+    pub fn get_output_dir(&self) -> Option<&Path> {
+        self.output_dir.as_deref()
+    }
+
+    pub fn get_attribute_mode(&self) -> AttributeMode {
+        self.attribute_mode
+    }
+
+    pub fn get_manifest_path(&self) -> &Path {
+        &self.manifest_path
+    }
+
+    /// Warnings found while loading the config file, such as unknown fields with
+    /// `Strictness::Warn`.
+    pub fn get_config_warnings(&self) -> &[ConfigDiagnostic] {
+        &self.config_warnings
+    }
+
+    /// Environment variables of each experiment, from the `env` block of the experiment in the
+    /// config file.
+    pub fn get_config_envs(&self) -> &IndexMap<String, Vec<(String, String)>> {
+        &self.config_envs
+    }
+
+    /// Metric extractor of each experiment, from `ExecutorBuilder::metrics` followed by the
+    /// `metrics` block of the experiment in the config file.
+    pub fn get_metric_extractors(&self) -> &IndexMap<String, MetricExtractor> {
+        &self.metric_extractors
+    }
+
+    /// Wrapper of each experiment that has one, from the `wrapper` key of the experiment in the
+    /// config file or else from `ExecutorBuilder::wrapper`.
+    pub fn get_wrappers(&self) -> &IndexMap<String, Wrapper> {
+        &self.wrappers
+    }
+
+    pub fn get_crash_wrapper(&self) -> Option<&Wrapper> {
+        self.crash_wrapper.as_ref()
+    }
+
+    pub fn get_core_dumps(&self) -> bool {
+        self.core_dumps
+    }
+
+    pub fn get_crash_backtrace(&self) -> bool {
+        self.crash_backtrace
+    }
+
+    pub fn get_limits(&self) -> ResourceLimits {
+        self.limits
+    }
+
+    /// Memory budget set with `ExecutorBuilder::memory_budget`. `None` when it is the memory
+    /// available when each campaign starts.
+    pub fn get_memory_budget(&self) -> Option<u64> {
+        self.memory_budget
+    }
+
+    pub fn get_learn_memory(&self) -> bool {
+        self.learn_memory
+    }
+
+    /// Configs in the order they appear in the config file.
+    pub fn get_configs(&self) -> &IndexMap<String, T> {
         &self.configs
     }
 
-    pub fn get_outputs(&self) -> &HashMap<String, Vec<Task<P>>> {
+    /// Outputs of each experiment, in config file order.
+    ///
+    /// Tasks of one experiment are kept in the order returned by `BuildParam::build_param`,
+    /// regardless of the order in which they were launched or finished.
+    pub fn get_outputs(&self) -> &IndexMap<String, Vec<Task<P, O>>> {
         &self.outputs
     }
 
-    pub async fn execute(&mut self) -> Result<(), Error> {
+    /// Build every param and list the tasks `execute` would launch, in launch order, with the
+    /// exact command line of each. Nothing is built or spawned.
+    ///
+    /// Changes made by the pre-run hook are not part of the plan, since it is only called
+    /// right before each task is launched.
+    pub fn plan(&self) -> Plan<P> {
         let ns3_dir = Path::new(&self.ns3_path);
-        println!("========== Build NS3 Program ==========");
-        build_ns3_program(ns3_dir).await?;
-        println!("Build NS3 Successfully!");
-        println!("========== Execute NS3 Tasks ==========");
-        let mut tasks = FuturesUnordered::new();
-        let mut params_map: HashMap<&String, Vec<P>> = self
-            .configs
-            .iter()
-            .map(|(k, v)| (k, v.build_param()))
+        let tasks = self
+            .queue()
+            .into_iter()
+            .map(|task| {
+                let (name, index, param) = task.requester;
+                PlannedTask {
+                    experiment: name.to_string(),
+                    index,
+                    param,
+                    command: task.command,
+                    estimate: task.estimate,
+                }
+            })
+            .collect();
+        Plan::new(
+            TaskCommand::build(ns3_dir),
+            tasks,
+            self.configs.keys().cloned(),
+        )
+    }
+
+    /// Manifest of a campaign that would run now, see `Manifest`. Like `plan`, nothing is built
+    /// or spawned.
+    pub fn manifest(&self) -> Result<Manifest, Error> {
+        let tasks = self
+            .queue()
+            .into_iter()
+            .map(|task| {
+                let (name, index, param) = task.requester;
+                ManifestTask::new(name, index, task.command, &param.build_attributes())
+            })
+            .collect();
+        self.build_manifest(tasks)
+    }
+
+    fn build_manifest(&self, tasks: Vec<ManifestTask>) -> Result<Manifest, Error> {
+        let config_path = Path::new(&self.config_path);
+        Ok(Manifest {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: timestamp(),
+            config_path: self.config_path.clone(),
+            config_format: self.config_format.clone(),
+            config_sha256: sha256_file(config_path)?,
+            configs: load_raw_configs(config_path, &self.config_format)?,
+            ns3: Ns3Info::probe(Path::new(&self.ns3_path)),
+            host: HostInfo::probe(),
+            env: self.config_envs.clone(),
+            tasks,
+        })
+    }
+
+    /// Subscribe to the events emitted by `execute`.
+    ///
+    /// Subscribe before calling `execute`, events emitted before subscribing are not replayed.
+    pub fn subscribe(&mut self) -> EventStream {
+        let (tx, rx) = unbounded_channel();
+        self.subscribers.push(tx);
+        EventStream::new(rx)
+    }
+
+    /// Call `hook` right before each task is launched, to prepare its working directory or
+    /// adjust its command, see `PreRun`.
+    ///
+    /// An `Err` returned by the hook fails the task without launching it, the same way a
+    /// failed execution does. Since the command line no longer tells alone what a task does,
+    /// runs are not deduplicated while a hook is set.
+    pub fn pre_run(
+        &mut self,
+        hook: impl Fn(&mut PreRun<'_, P>) -> Result<(), Error> + Send + Sync + 'static,
+    ) {
+        self.pre_run = Some(PreRunHook(Arc::new(hook)));
+    }
+
+    /// Write the record of every task to `sink` as soon as the task finishes, see
+    /// `ResultSink`.
+    ///
+    /// `execute` finishes the sinks once all the tasks are done. With `execute_stream`, call
+    /// `finish_sinks` after the stream ends.
+    pub fn add_sink(&mut self, sink: impl ResultSink<P> + 'static) {
+        self.sinks.push(SinkHandle(Arc::new(Mutex::new(sink))));
+    }
+
+    /// Call `ResultSink::finish` on every sink, returning the first error.
+    pub fn finish_sinks(&self) -> Result<(), Error> {
+        let mut result = Ok(());
+        for sink in &self.sinks {
+            let finished = sink.0.lock().unwrap().finish();
+            result = result.and(finished);
+        }
+        result
+    }
+
+    /// Take the output of a command from `cache` instead of running it, when the command
+    /// already succeeded in an earlier campaign, see `ResultCache`.
+    ///
+    /// The output of a cached task is processed like a new one: metrics are extracted again,
+    /// the post-process hook is called and the task is written to the sinks. Commands are
+    /// looked up along with the `Revision` of the campaign.
+    pub fn resume_from(&mut self, cache: impl ResultCache + 'static) {
+        self.cache = Some(CacheHandle(Arc::new(Mutex::new(cache))));
+    }
+
+    /// Estimate the runtime of the tasks whose param does not state it from `history`, see
+    /// `LaunchOrder::LongestFirst`. Estimates also drive the remaining time reported by the
+    /// provided progress reporters.
+    pub fn estimate_from(&mut self, history: impl RuntimeHistory + 'static) {
+        self.history = Some(HistoryHandle(Arc::new(Mutex::new(history))));
+    }
+
+    /// Params of all experiments in launch order, along with the command and the estimated
+    /// runtime of their task.
+    ///
+    /// A task is estimated by its param, or else by the runtime history, whose errors leave it
+    /// unestimated since estimates only order tasks and report progress. Once a task has an
+    /// estimate, the others are assumed to run as long as the estimated tasks of their
+    /// experiment on average, or of all experiments.
+    fn queue(&self) -> Vec<QueuedTask<'_, P>> {
+        let mut queue: Vec<QueuedTask<P>> = launch_params(&self.configs, self.launch_order)
+            .into_iter()
+            .map(|(name, index, param)| {
+                let command = self.task_command(
+                    name,
+                    index,
+                    self.task_argument(name, index, &param),
+                    self.task_env(name, &param),
+                );
+                let estimate = param.build_resources().runtime.or_else(|| {
+                    let history = self.history.as_ref()?;
+                    let runtime = history.0.lock().unwrap().runtime(name, &command);
+                    runtime.ok().flatten()
+                });
+                QueuedTask {
+                    requester: (name, index, param),
+                    command,
+                    estimate,
+                }
+            })
+            .collect();
+        fill_estimates(&mut queue);
+        if self.launch_order == LaunchOrder::LongestFirst {
+            // The sort is stable, tasks estimated alike keep their sequential order.
+            queue.sort_by_key(|task| std::cmp::Reverse(task.estimate));
+        }
+        if self.dedup && self.pre_run.is_none() {
+            share_work_dirs(&mut queue);
+        }
+        queue
+    }
+
+    fn lookup(
+        &self,
+        command: &TaskCommand,
+        revision: &Revision,
+    ) -> Result<Option<CachedTask>, Error> {
+        match &self.cache {
+            Some(cache) => cache.0.lock().unwrap().lookup(command, revision),
+            None => Ok(None),
+        }
+    }
+
+    /// Call `ResultSink::start` on every sink, returning the first error.
+    fn start_sinks(&self, manifest: &Manifest) -> Result<(), Error> {
+        let mut result = Ok(());
+        for sink in &self.sinks {
+            let started = sink.0.lock().unwrap().start(manifest);
+            result = result.and(started);
+        }
+        result
+    }
+
+    /// Write the record of `task` to every sink, returning the first error, and emit the
+    /// columns the sinks left out of it.
+    fn record(
+        &self,
+        record: &TaskRecord<P>,
+        task: &TaskInfo,
+        monitor: &Monitor<'_>,
+    ) -> Result<(), Error> {
+        let mut result = Ok(());
+        for sink in &self.sinks {
+            let mut sink = sink.0.lock().unwrap();
+            let known = sink.dropped_columns().len();
+            result = result.and(sink.write(record));
+            let columns = sink.dropped_columns().get(known..).unwrap_or_default();
+            if !columns.is_empty() {
+                monitor.emit(EventKind::ColumnsDropped {
+                    task: task.clone(),
+                    columns: columns.to_vec(),
+                });
+            }
+        }
+        result
+    }
+
+    /// Directory a task runs in: `<output_dir>/<experiment>/<index>`, or the ns-3 directory
+    /// when `output_dir` is not set. A deduplicated task runs in the directory of the first
+    /// param of its run instead, see `ExecutorBuilder::dedup`.
+    pub fn work_dir(&self, experiment: &str, index: usize) -> PathBuf {
+        match &self.output_dir {
+            Some(output_dir) => output_dir.join(experiment).join(index.to_string()),
+            None => PathBuf::from(&self.ns3_path),
+        }
+    }
+
+    fn task_command(
+        &self,
+        experiment: &str,
+        index: usize,
+        argument: String,
+        env: Vec<(String, String)>,
+    ) -> TaskCommand {
+        let mut command = TaskCommand::run(&self.ns3_path, argument);
+        command.env = env;
+        if self.output_dir.is_some() {
+            command = command.with_work_dir(self.work_dir(experiment, index));
+        }
+        match self.wrappers.get(experiment) {
+            Some(wrapper) => {
+                command.with_command_template(&wrapper.template(&self.work_dir(experiment, index)))
+            }
+            None => command,
+        }
+    }
+
+    /// Program and arguments of a task, attribute overrides included.
+    fn task_argument(&self, experiment: &str, index: usize, param: &P) -> String {
+        let mut argument = param.build_cmd();
+        let attributes = param.build_attributes();
+        let args = match self.attribute_mode {
+            AttributeMode::CommandLine => attributes.iter().map(|a| a.to_arg()).collect(),
+            AttributeMode::ConfigStore if attributes.is_empty() => vec![],
+            AttributeMode::ConfigStore => {
+                config_store_args(&self.work_dir(experiment, index).join(CONFIG_STORE_FILE))
+            }
+        };
+        for arg in args {
+            argument.push(' ');
+            argument.push_str(&shell_quote(&arg));
+        }
+        argument
+    }
+
+    /// Environment variables of the experiment, overridden by those of the param.
+    fn task_env(&self, experiment: &str, param: &P) -> Vec<(String, String)> {
+        let mut env: IndexMap<String, String> = self
+            .config_envs
+            .get(experiment)
+            .into_iter()
+            .flatten()
+            .cloned()
             .collect();
-        let total_count = params_map.values().map(|v| v.len()).sum::<usize>() as u64;
-        let mb = MultiBar::new();
-        mb.println("Launch NS3 Tasks: ");
-        let mut pb1 = mb.create_bar(total_count);
-        mb.println("Complete NS3 Tasks: ");
-        let mut pb2 = mb.create_bar(total_count);
-        let progress = spawn_blocking(move || {
-            mb.listen();
+        env.extend(param.build_env());
+        env.into_iter().collect()
+    }
+
+    fn monitor(&self) -> Monitor<'_> {
+        Monitor {
+            progress: self.progress.as_ref(),
+            subscribers: &self.subscribers,
+        }
+    }
+
+    /// Build ns-3, then launch the tasks and yield each of them as soon as it finishes, along
+    /// with the name of its experiment.
+    ///
+    /// Tasks run only while the stream is polled, as many at the same time as their CPU slots
+    /// and memory fit in `task_concurrent` and the memory budget, see `schedule`. A failed task
+    /// is yielded as a `TaskError` without stopping the others. Dropping the stream kills the
+    /// tasks still running. The progress reporter is ticked every second while the stream is
+    /// polled, see `ProgressReporter::tick`.
+    ///
+    /// Unlike `execute`, results are not stored in `outputs`.
+    pub async fn execute_stream(&self) -> Result<impl Stream<Item = TaskResult<P, O>> + '_, Error>
+    where
+        P: Send + 'static,
+        O: Send + 'static,
+    {
+        let ns3_dir = Path::new(&self.ns3_path);
+        let campaign = Arc::new(Campaign::new(self.monitor()));
+        let monitor = &campaign.monitor;
+        monitor.emit(EventKind::BuildStarted);
+        let build = build_ns3_program(ns3_dir).await;
+        monitor.emit(EventKind::BuildFinished {
+            success: build.is_ok(),
+            elapsed: campaign.start.elapsed(),
         });
-        for params in params_map.drain() {
-            for param in params.1 {
-                pb1.inc();
-                tasks.push(execute_ns3_program(
-                    params.0,
-                    ns3_dir,
-                    param,
-                    self.retry_limit,
+        build?;
+        let queue = self.queue();
+        let mut counts: IndexMap<String, usize> =
+            self.configs.keys().map(|k| (k.to_owned(), 0)).collect();
+        let mut estimates: IndexMap<String, Duration> = IndexMap::new();
+        for task in &queue {
+            let name = task.requester.0;
+            *counts.entry(name.to_string()).or_default() += 1;
+            if let Some(estimate) = task.estimate {
+                *estimates.entry(name.to_string()).or_default() += estimate;
+            }
+        }
+        monitor.emit(EventKind::CampaignStarted { counts, estimates });
+        let mut manifest_tasks = vec![];
+        let mut task_estimates = HashMap::new();
+        let commands = queue
+            .into_iter()
+            .map(|task| {
+                let (name, index, param) = &task.requester;
+                let attributes = param.build_attributes();
+                manifest_tasks.push(ManifestTask::new(
+                    name,
+                    *index,
+                    task.command.clone(),
+                    &attributes,
                 ));
-                // If full, wait for one to finish.
-                if tasks.len() >= self.task_concurrent {
-                    if let Some(t) = tasks.next().await {
-                        let (n, t) = t?;
-                        pb2.inc();
-                        if let Some(v) = self.outputs.get_mut(n) {
-                            v.push(t);
-                        }
+                if let Some(estimate) = task.estimate {
+                    task_estimates.insert((*name, *index), estimate);
+                }
+                (task.command, task.requester)
+            })
+            .collect();
+        let manifest = self.build_manifest(manifest_tasks)?;
+        if let Some(dir) = self.manifest_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        manifest.write_to(&self.manifest_path)?;
+        self.start_sinks(&manifest)?;
+        campaign.set_manifest(self.manifest_path.clone(), manifest);
+        let dedup = self.dedup && self.pre_run.is_none();
+        let runs: Vec<Run<P>> = group_runs(commands, dedup)
+            .into_iter()
+            .map(|(command, requesters)| {
+                let run = Run::new(command, requesters, &task_estimates);
+                monitor.emit_each(&run.infos, |task| EventKind::TaskQueued { task });
+                run
+            })
+            .collect();
+        let finished = campaign.clone();
+        let learned = campaign.clone();
+        let jobs = runs
+            .into_iter()
+            .map(|run| {
+                // A deduplicated run needs what its first param says.
+                let (name, _, param) = &run.requesters[0];
+                let key = (*name, param.build_resources());
+                (key, run.execute(self, campaign.clone()))
+            })
+            .collect();
+        let capacity = Demand {
+            cpus: self.task_concurrent.max(1),
+            memory: self
+                .memory_budget
+                .or_else(available_memory)
+                .unwrap_or(u64::MAX),
+        };
+        let demand = move |(name, estimate): &(&str, ResourceEstimate)| Demand {
+            cpus: estimate.cpus,
+            memory: estimate
+                .memory
+                .or_else(|| self.learn_memory.then(|| learned.peak_rss(name)).flatten())
+                .unwrap_or(0),
+        };
+        let mut results = Box::pin(schedule(jobs, capacity, demand).flat_map(stream::iter));
+        let progress = self.progress.as_ref();
+        let mut ticks = tokio::time::interval(PROGRESS_TICK);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let results = stream::poll_fn(move |cx| {
+            while ticks.poll_tick(cx).is_ready() {
+                progress.tick();
+            }
+            results.as_mut().poll_next(cx)
+        });
+        Ok(results
+            .chain(stream::once(async move { finished.finish() }).filter_map(|_| async { None })))
+    }
+
+    /// Build ns-3, then run all the tasks and store their results in `outputs`.
+    ///
+    /// Execution stops at the first failed task, killing the tasks still running. See
+    /// `execute_stream` to go on with the other tasks instead.
+    pub async fn execute(&mut self) -> Result<(), Error>
+    where
+        P: Send + 'static,
+        O: Send + 'static,
+    {
+        let mut results = vec![];
+        {
+            let mut stream = pin!(self.execute_stream().await?);
+            while let Some(result) = stream.next().await {
+                match result {
+                    Ok(result) => results.push(result),
+                    Err(e) => {
+                        self.finish_sinks()?;
+                        return Err(e.error);
                     }
                 }
             }
         }
-        pb1.finish();
-        // Wait for the remaining to finish.
-        while let Some(t) = tasks.next().await {
-            // handle response
-            let (n, t) = t?;
-            pb2.inc();
-            if let Some(v) = self.outputs.get_mut(n) {
-                v.push(t);
+        self.finish_sinks()?;
+        results.sort_by_key(|(_, t)| t.index);
+        for (name, task) in results {
+            if let Some(outputs) = self.outputs.get_mut(&name) {
+                outputs.push(task);
             }
         }
-        pb2.finish();
-        progress.await.unwrap();
         Ok(())
     }
 }
 
-fn check_config_file(config_path: &String, ext: &ConfigFormat) -> Result<PathBuf, Error> {
-    let config_file_path = match Path::new(&config_path).canonicalize() {
-        Ok(path) => path,
-        Err(e) => {
-            return Err(Error::FileNotFound(format!(
-                "Can not locate config file: {:?}.",
-                e
-            )));
+/// Item of `Executor::execute_stream`.
+pub type TaskResult<P, O = ()> = Result<(String, Task<P, O>), TaskError>;
+
+/// Shared by the tasks of one `execute_stream`, to emit `CampaignDone` exactly once: when the
+/// stream ends, or when it is dropped before that.
+struct Campaign<'a> {
+    monitor: Monitor<'a>,
+    start: Instant,
+    succeeded: AtomicUsize,
+    failed: AtomicUsize,
+    done: AtomicBool,
+    manifest: Mutex<Option<CampaignManifest>>,
+    /// Largest peak RSS observed among the tasks of each experiment.
+    peak_rss: Mutex<HashMap<String, u64>>,
+}
+
+/// The manifest of a campaign, where it is written and where each task is in it.
+struct CampaignManifest {
+    path: PathBuf,
+    manifest: Manifest,
+    positions: HashMap<(String, usize), usize>,
+}
+
+impl<'a> Campaign<'a> {
+    fn new(monitor: Monitor<'a>) -> Self {
+        Campaign {
+            monitor,
+            start: Instant::now(),
+            succeeded: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            done: AtomicBool::new(false),
+            manifest: Mutex::new(None),
+            peak_rss: Mutex::new(HashMap::new()),
         }
-    };
-    match config_file_path.extension() {
-        Some(t) => match ext {
-            ConfigFormat::Ron => {
-                if t != "ron" {
-                    return Err(Error::InvalidConfig(
-                        "Config file must be a ron file.".to_string(),
-                    ));
+    }
+
+    fn peak_rss(&self, experiment: &str) -> Option<u64> {
+        self.peak_rss.lock().unwrap().get(experiment).copied()
+    }
+
+    fn record_peak_rss(&self, experiment: &str, peak_rss: u64) {
+        let mut learned = self.peak_rss.lock().unwrap();
+        let peak = learned.entry(experiment.to_string()).or_default();
+        *peak = (*peak).max(peak_rss);
+    }
+
+    fn set_manifest(&self, path: PathBuf, manifest: Manifest) {
+        let positions = manifest
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(i, t)| ((t.experiment.clone(), t.index), i))
+            .collect();
+        *self.manifest.lock().unwrap() = Some(CampaignManifest {
+            path,
+            manifest,
+            positions,
+        });
+    }
+
+    /// Revision of the campaign, for the result cache.
+    fn revision(&self) -> Revision {
+        let campaign = self.manifest.lock().unwrap();
+        let manifest = campaign.as_ref().map(|c| &c.manifest);
+        manifest.map(Revision::of).unwrap_or_default()
+    }
+
+    /// Record in the manifest the command a run actually executes.
+    fn update_manifest<P>(&self, requesters: &[Requester<'_, P>], command: &TaskCommand) {
+        if let Some(campaign) = self.manifest.lock().unwrap().as_mut() {
+            for (name, index, _) in requesters {
+                if let Some(i) = campaign.positions.get(&(name.to_string(), *index)) {
+                    campaign.manifest.tasks[*i].command = command.clone();
                 }
             }
-            ConfigFormat::Json => {
-                if t != "json" {
-                    return Err(Error::InvalidConfig(
-                        "Config file must be a json file.".to_string(),
-                    ));
-                }
+        }
+    }
+
+    fn finish(&self) {
+        if !self.done.swap(true, Ordering::SeqCst) {
+            if let Some(campaign) = self.manifest.lock().unwrap().as_ref() {
+                // The manifest written at the start of the campaign is left as is on failure,
+                // there is no one left to report the error to.
+                let _ = campaign.manifest.write_to(&campaign.path);
             }
-            ConfigFormat::Toml => {
-                if t != "toml" {
-                    return Err(Error::InvalidConfig(
-                        "Config file must be a toml file.".to_string(),
-                    ));
-                }
+            self.monitor.emit(EventKind::CampaignDone {
+                succeeded: self.succeeded.load(Ordering::SeqCst),
+                failed: self.failed.load(Ordering::SeqCst),
+                elapsed: self.start.elapsed(),
+            });
+        }
+    }
+}
+
+impl Drop for Campaign<'_> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// A command to run, and the params that requested it.
+struct Run<'a, P> {
+    command: TaskCommand,
+    infos: Vec<TaskInfo>,
+    requesters: Vec<Requester<'a, P>>,
+}
+
+impl<'a, P: BuildCmd> Run<'a, P> {
+    fn new(
+        command: TaskCommand,
+        requesters: Vec<Requester<'a, P>>,
+        estimates: &HashMap<(&str, usize), Duration>,
+    ) -> Self {
+        let infos = requesters
+            .iter()
+            .map(|(name, index, _)| TaskInfo {
+                experiment: name.to_string(),
+                index: *index,
+                command: command.clone(),
+                estimate: estimates.get(&(*name, *index)).copied(),
+            })
+            .collect();
+        Run {
+            command,
+            infos,
+            requesters,
+        }
+    }
+
+    // Items of `execute_stream`, whose error type is part of the public API.
+    #[allow(clippy::result_large_err)]
+    async fn execute<T: Default + BuildParam<P>, O: Send + 'static>(
+        mut self,
+        exe: &Executor<T, P, O>,
+        campaign: Arc<Campaign<'_>>,
+    ) -> Vec<TaskResult<P, O>>
+    where
+        P: Send + 'static,
+    {
+        let revision = campaign.revision();
+        let cached = self
+            .prepare(exe)
+            .and_then(|_| exe.lookup(&self.command, &revision));
+        let cached = match cached {
+            Ok(cached) => cached,
+            Err(error) => {
+                campaign
+                    .monitor
+                    .emit_each(&self.infos, |task| EventKind::TaskFailed {
+                        task,
+                        attempts: 0,
+                        elapsed: Duration::ZERO,
+                        error: Some(error.clone()),
+                    });
+                return self.fail(exe, error, None, 0, Duration::ZERO, &campaign);
+            }
+        };
+        if exe.pre_run.is_some() {
+            campaign.update_manifest(&self.requesters, &self.command);
+        }
+        let is_cached = cached.is_some();
+        let planned = self.command.clone();
+        let (output, attempts, elapsed, peak_rss) = match cached {
+            Some(cached) => {
+                let output = Output {
+                    status: ExitStatus::from_raw((cached.exit_code & 0xff) << 8),
+                    stdout: cached.stdout,
+                    stderr: cached.stderr,
+                };
+                (Ok(output), cached.attempts, cached.elapsed, None)
+            }
+            None => {
+                let work_dir = self.work_dir(exe);
+                // A deduplicated run is limited as its first param says.
+                let limits = exe.limits.with(&self.requesters[0].2.build_limits());
+                let crash = CrashPolicy {
+                    template: exe.crash_wrapper.as_ref().map(|w| w.template(&work_dir)),
+                    core_dir: exe.core_dumps.then_some(work_dir),
+                    backtrace: exe.crash_backtrace,
+                };
+                execute_ns3_program(
+                    &mut self.command,
+                    &self.infos,
+                    exe.retry_limit,
+                    exe.task_timeout,
+                    &limits,
+                    &crash,
+                    &campaign.monitor,
+                )
+                .await
+            }
+        };
+        if let Some(peak_rss) = peak_rss {
+            for (name, _, _) in &self.requesters {
+                campaign.record_peak_rss(name, peak_rss);
             }
-            ConfigFormat::Yaml => {
-                if t != "yaml" {
-                    return Err(Error::InvalidConfig(
-                        "Config file must be a yaml file.".to_string(),
-                    ));
+        }
+        let crash_wrapped = self.command != planned;
+        if crash_wrapped {
+            for info in &mut self.infos {
+                info.command = self.command.clone();
+            }
+        }
+        let output = match output {
+            Ok(output) => output,
+            Err((error, output)) => {
+                return self.fail(exe, error, output, attempts, elapsed, &campaign)
+            }
+        };
+        let work_dir = self.work_dir(exe);
+        let tasks = fan_out(
+            self.requesters,
+            &self.command,
+            output,
+            (attempts, elapsed, peak_rss),
+            &work_dir,
+        );
+        let mut results = Vec::with_capacity(tasks.len());
+        for ((name, index, mut task), info) in tasks.into_iter().zip(self.infos) {
+            task.cached = is_cached;
+            let wrappers = match crash_wrapped {
+                true => exe.crash_wrapper.as_ref(),
+                false => exe.wrappers.get(name),
+            };
+            task.reports = wrappers
+                .map(|w| w.find_reports(&task.work_dir))
+                .unwrap_or_default();
+            let metrics = match exe.metric_extractors.get(name) {
+                Some(extractor) => extractor.extract(&task.stdout, &task.stderr),
+                None => Ok(IndexMap::new()),
+            };
+            let (task, processed) = match metrics {
+                Ok(metrics) => {
+                    task.metrics = metrics;
+                    exe.post_process.process(task).await
                 }
+                Err(error) => (task, Err(error)),
+            };
+            let record = TaskRecord {
+                experiment: name,
+                index,
+                param: &task.param,
+                command: &task.command,
+                cached: task.cached,
+                attempts: task.attempts,
+                elapsed: task.elapsed,
+                exit_code: task.output.status.code(),
+                metrics: &task.metrics,
+                stdout: &task.stdout,
+                stderr: &task.stderr,
+                error: processed.as_ref().err(),
+            };
+            let recorded = exe.record(&record, &info, &campaign.monitor);
+            let processed = processed.and_then(|processed| recorded.map(|_| processed));
+            let result = match processed {
+                Ok(processed) => Ok((name.to_string(), task.with_processed(processed))),
+                Err(error) => Err(TaskError {
+                    experiment: name.to_string(),
+                    index,
+                    command: task.command,
+                    error,
+                }),
+            };
+            match &result {
+                Ok(_) => campaign.succeeded.fetch_add(1, Ordering::SeqCst),
+                Err(_) => campaign.failed.fetch_add(1, Ordering::SeqCst),
+            };
+            // Reported once the output was processed and recorded, which may still fail the
+            // task. A cached task reports no attempt.
+            let (attempts, elapsed) = match is_cached {
+                true => (0, Duration::ZERO),
+                false => (attempts, elapsed),
+            };
+            campaign.monitor.emit(match &result {
+                Ok((_, task)) if task.output.status.success() => EventKind::TaskSucceeded {
+                    task: info,
+                    attempts,
+                    elapsed,
+                },
+                // Only with a retry limit of 0, whose failed output is returned.
+                Ok(_) => EventKind::TaskFailed {
+                    task: info,
+                    attempts,
+                    elapsed,
+                    error: None,
+                },
+                Err(e) => EventKind::TaskFailed {
+                    task: info,
+                    attempts,
+                    elapsed,
+                    error: Some(e.error.clone()),
+                },
+            });
+            results.push(result.map(|(name, mut task)| {
+                if !exe.keep_raw_output {
+                    task.drop_raw_output();
+                }
+                (name, task)
+            }));
+        }
+        results
+    }
+
+    /// Directory the run executes in, the one of its first requester. Deduplicated params share
+    /// it, see `share_work_dirs`, and params are not deduplicated with a pre-run hook.
+    fn work_dir<T: Default + BuildParam<P>, O>(&self, exe: &Executor<T, P, O>) -> PathBuf {
+        let (name, index, _) = &self.requesters[0];
+        exe.work_dir(name, *index)
+    }
+
+    /// Create the working directories of the run, write the ConfigStore files and call the
+    /// pre-run hook.
+    fn prepare<T: Default + BuildParam<P>, O>(
+        &mut self,
+        exe: &Executor<T, P, O>,
+    ) -> Result<(), Error> {
+        if exe.output_dir.is_some() {
+            // A run with a ConfigStore file has a single requester, since the path of the file
+            // is part of the command.
+            let work_dir = self.work_dir(exe);
+            std::fs::create_dir_all(&work_dir)?;
+            let attributes = self.requesters[0].2.build_attributes();
+            if exe.attribute_mode == AttributeMode::ConfigStore && !attributes.is_empty() {
+                std::fs::write(work_dir.join(CONFIG_STORE_FILE), raw_text(&attributes))?;
             }
-        },
-        None => {
-            return Err(Error::InvalidConfig(
-                "Config file must have a valid file extension.".to_string(),
-            ));
         }
+        let Some(hook) = &exe.pre_run else {
+            return Ok(());
+        };
+        let work_dir = self.work_dir(exe);
+        let (name, index, param) = &self.requesters[0];
+        let mut pre_run = PreRun {
+            experiment: name,
+            index: *index,
+            param,
+            work_dir: &work_dir,
+            argument: exe.task_argument(name, *index, param),
+            env: self.command.env.clone(),
+        };
+        (hook.0)(&mut pre_run)?;
+        let command = exe.task_command(name, *index, pre_run.argument, pre_run.env);
+        for info in &mut self.infos {
+            info.command = command.clone();
+        }
+        self.command = command;
+        Ok(())
+    }
+
+    /// Fail every task of the run with `error`. `output` is the output of the last attempt,
+    /// when it exited.
+    #[allow(clippy::result_large_err)]
+    fn fail<T: Default + BuildParam<P>, O>(
+        self,
+        exe: &Executor<T, P, O>,
+        error: Error,
+        output: Option<Output>,
+        attempts: u32,
+        elapsed: Duration,
+        campaign: &Campaign<'_>,
+    ) -> Vec<TaskResult<P, O>> {
+        campaign
+            .failed
+            .fetch_add(self.infos.len(), Ordering::SeqCst);
+        let metrics = IndexMap::new();
+        let stdout = output.as_ref().map(|o| String::from_utf8_lossy(&o.stdout));
+        let stderr = output.as_ref().map(|o| String::from_utf8_lossy(&o.stderr));
+        for ((name, index, param), info) in self.requesters.iter().zip(&self.infos) {
+            let record = TaskRecord {
+                experiment: name,
+                index: *index,
+                param,
+                command: &self.command,
+                cached: false,
+                attempts,
+                elapsed,
+                exit_code: output.as_ref().and_then(|o| o.status.code()),
+                metrics: &metrics,
+                stdout: stdout.as_deref().unwrap_or_default(),
+                stderr: stderr.as_deref().unwrap_or_default(),
+                error: Some(&error),
+            };
+            // The task failed already, an error of the sinks would not change that.
+            let _ = exe.record(&record, info, &campaign.monitor);
+        }
+        self.infos
+            .into_iter()
+            .map(|info| {
+                Err(TaskError {
+                    experiment: info.experiment,
+                    index: info.index,
+                    command: info.command,
+                    error: error.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Params of all experiments as `(experiment, index, param)`, in launch order.
+fn launch_params<T: BuildParam<P>, P: BuildCmd>(
+    configs: &IndexMap<String, T>,
+    launch_order: LaunchOrder,
+) -> Vec<(&str, usize, P)> {
+    let mut params: Vec<(&str, usize, P)> = configs
+        .iter()
+        .flat_map(|(k, v)| {
+            v.build_param()
+                .into_iter()
+                .enumerate()
+                .map(move |(i, p)| (k.as_str(), i, p))
+        })
+        .collect();
+    if let LaunchOrder::Shuffle(seed) = launch_order {
+        params.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+    }
+    params
+}
+
+/// A param of an experiment, as `(experiment, index, param)`.
+type Requester<'a, P> = (&'a str, usize, P);
+
+/// A param about to be launched, with the command and the estimated runtime of its task.
+struct QueuedTask<'a, P> {
+    requester: Requester<'a, P>,
+    command: TaskCommand,
+    estimate: Option<Duration>,
+}
+
+/// Estimate the tasks without an estimate as the mean of the estimated tasks of their
+/// experiment, or of all experiments. Nothing is estimated when no task is.
+fn fill_estimates<P>(queue: &mut [QueuedTask<P>]) {
+    let mut sums: HashMap<&str, (Duration, u32)> = HashMap::new();
+    let mut total = (Duration::ZERO, 0);
+    for task in queue.iter() {
+        if let Some(estimate) = task.estimate {
+            let sum = sums.entry(task.requester.0).or_default();
+            *sum = (sum.0 + estimate, sum.1 + 1);
+            total = (total.0 + estimate, total.1 + 1);
+        }
+    }
+    if total.1 == 0 {
+        return;
+    }
+    for task in queue.iter_mut() {
+        if task.estimate.is_none() {
+            let (sum, count) = sums.get(task.requester.0).copied().unwrap_or(total);
+            task.estimate = Some(sum / count);
+        }
+    }
+}
+
+/// Give every task whose command only differs from an earlier one by its working directory the
+/// command of the earlier one, so that both are grouped into one run in its directory.
+fn share_work_dirs<P>(queue: &mut [QueuedTask<P>]) {
+    let mut firsts: HashMap<TaskCommand, TaskCommand> = HashMap::new();
+    for task in queue {
+        let first = firsts
+            .entry(task.command.without_work_dir())
+            .or_insert_with(|| task.command.clone());
+        task.command = first.clone();
+    }
+}
+
+/// Group params paired with their command into runs, in launch order.
+///
+/// With `dedup`, params sharing the same command are grouped into a single run placed at the
+/// first of them, so the command is only executed once.
+fn group_runs<'a, P: BuildCmd>(
+    commands: Vec<(TaskCommand, Requester<'a, P>)>,
+    dedup: bool,
+) -> Vec<(TaskCommand, Vec<Requester<'a, P>>)> {
+    if dedup {
+        let mut runs: IndexMap<TaskCommand, Vec<Requester<P>>> = IndexMap::new();
+        for (command, requester) in commands {
+            runs.entry(command).or_default().push(requester);
+        }
+        runs.into_iter().collect()
+    } else {
+        commands
+            .into_iter()
+            .map(|(command, requester)| (command, vec![requester]))
+            .collect()
     }
-    Ok(config_file_path)
 }
 
 impl Default for ExecutorBuilder {
@@ -203,9 +1191,29 @@ impl ExecutorBuilder {
             ns3_path: None,
             task_concurrent: None,
             retry_limit: None,
+            launch_order: None,
+            strictness: None,
+            dedup: None,
+            task_timeout: None,
+            progress: None,
+            post_process: NoPostProcess,
+            keep_raw_output: None,
+            output_dir: None,
+            attribute_mode: None,
+            metrics: None,
+            manifest_path: None,
+            wrapper: None,
+            crash_wrapper: None,
+            core_dumps: None,
+            crash_backtrace: None,
+            limits: None,
+            memory_budget: None,
+            learn_memory: None,
         }
     }
+}
 
+impl<H> ExecutorBuilder<H> {
     pub fn config_path(mut self, config_path: &str) -> Self {
         self.config_path = Some(config_path.to_string());
         self
@@ -231,19 +1239,223 @@ impl ExecutorBuilder {
         self
     }
 
+    pub fn launch_order(mut self, launch_order: LaunchOrder) -> Self {
+        self.launch_order = Some(launch_order);
+        self
+    }
+
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = Some(strictness);
+        self
+    }
+
+    /// Whether params producing the same command line, within an experiment or across
+    /// experiments, are run only once. Default to `true`.
+    ///
+    /// The output of a deduplicated run is copied into the `Task` of every param that
+    /// requested it, so `get_outputs` is the same as without deduplication. With `output_dir`,
+    /// command lines are compared without their working directory: a deduplicated run executes
+    /// in the directory of its first param, which is the `Task::work_dir` of every param.
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// Kill an attempt of a task, the ns-3 program included, when it runs longer than
+    /// `task_timeout`. A timed out attempt counts as a failed one and is retried. Default to no
+    /// timeout.
+    pub fn task_timeout(mut self, task_timeout: Duration) -> Self {
+        self.task_timeout = Some(task_timeout);
+        self
+    }
+
+    /// How the progress of `Executor::execute` is reported.
+    ///
+    /// Default to `MultiBarReporter` when stdout is a terminal and to `LogReporter` otherwise.
+    /// Use `SilentReporter` to report nothing.
+    pub fn progress(mut self, progress: impl ProgressReporter + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Process the output of each task as soon as it finishes, see `PostProcess`. The result
+    /// is stored in `Task::processed`, which also changes the type of the executor to
+    /// `Executor<T, P, O>`, `O` being the output of the hook.
+    pub fn post_process<Q>(self, post_process: Q) -> ExecutorBuilder<Q> {
+        ExecutorBuilder {
+            config_path: self.config_path,
+            config_format: self.config_format,
+            ns3_path: self.ns3_path,
+            task_concurrent: self.task_concurrent,
+            retry_limit: self.retry_limit,
+            launch_order: self.launch_order,
+            strictness: self.strictness,
+            dedup: self.dedup,
+            task_timeout: self.task_timeout,
+            progress: self.progress,
+            post_process,
+            keep_raw_output: self.keep_raw_output,
+            output_dir: self.output_dir,
+            attribute_mode: self.attribute_mode,
+            metrics: self.metrics,
+            manifest_path: self.manifest_path,
+            wrapper: self.wrapper,
+            crash_wrapper: self.crash_wrapper,
+            core_dumps: self.core_dumps,
+            crash_backtrace: self.crash_backtrace,
+            limits: self.limits,
+            memory_budget: self.memory_budget,
+            learn_memory: self.learn_memory,
+        }
+    }
+
+    /// Whether the stdout and stderr of a task are kept once it was post-processed. Default to
+    /// `true`.
+    ///
+    /// Set it to `false` when `post_process` extracts all that is needed, so large outputs are
+    /// freed as soon as each task finishes. The exit status is kept in any case.
+    pub fn keep_raw_output(mut self, keep_raw_output: bool) -> Self {
+        self.keep_raw_output = Some(keep_raw_output);
+        self
+    }
+
+    /// Run each task in its own directory, `<output_dir>/<experiment>/<index>`, created right
+    /// before the task is launched. Default to running all tasks in the ns-3 directory.
+    ///
+    /// The directory is passed to waf with `--cwd`, so files written by the ns-3 program with a
+    /// relative path end up there.
+    pub fn output_dir(mut self, output_dir: &str) -> Self {
+        self.output_dir = Some(output_dir.to_string());
+        self
+    }
+
+    /// How the attribute overrides of `BuildCmd::build_attributes` are passed to the ns-3
+    /// program. Default to `AttributeMode::CommandLine`.
+    ///
+    /// `AttributeMode::ConfigStore` writes a file in the directory of each task, so it requires
+    /// `output_dir`.
+    pub fn attribute_mode(mut self, attribute_mode: AttributeMode) -> Self {
+        self.attribute_mode = Some(attribute_mode);
+        self
+    }
+
+    /// Rules extracting metrics from the output of every task into `Task::metrics`, see
+    /// `MetricRule`. The rules of the `metrics` block of an experiment in the config file are
+    /// applied after these ones. A task missing a required metric fails with
+    /// `Error::MissingMetric`.
+    pub fn metrics(mut self, metrics: Vec<MetricRule>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Where `execute` writes the manifest of each campaign, see `Manifest`. Default to
+    /// `manifest.json` in `output_dir`, or without `output_dir` to `manifest-<unix millis>.json`
+    /// in the ns-3 directory, named after the time the executor is built so that concurrent
+    /// campaigns keep their own manifest.
+    pub fn manifest_path(mut self, manifest_path: &str) -> Self {
+        self.manifest_path = Some(manifest_path.to_string());
+        self
+    }
+
+    /// Run the ns-3 program of every task under `wrapper`, see `Wrapper`. The `wrapper` key of
+    /// an experiment in the config file takes precedence. Requires `output_dir`, where the
+    /// reports are written.
+    pub fn wrapper(mut self, wrapper: Wrapper) -> Self {
+        self.wrapper = Some(wrapper);
+        self
+    }
+
+    /// Retry a task whose program was killed by a signal under `wrapper`, e.g. `Wrapper::Gdb`
+    /// to get a backtrace of the crash. Only applies when `retry_limit` allows a retry.
+    /// Requires `output_dir`, where the reports are written.
+    pub fn crash_wrapper(mut self, crash_wrapper: Wrapper) -> Self {
+        self.crash_wrapper = Some(crash_wrapper);
+        self
+    }
+
+    /// Raise the core file size limit of the tasks, and move the core file of a program killed
+    /// by a signal to `core.<attempt>` in the directory of its task, see the `crash` module.
+    /// Default to `false`. Requires `output_dir`.
+    pub fn core_dumps(mut self, core_dumps: bool) -> Self {
+        self.core_dumps = Some(core_dumps);
+        self
+    }
+
+    /// Take a backtrace of the core file of a crashed task with `gdb -batch`, saved as
+    /// `backtrace.<attempt>.txt` and included in `Error::Crashed`. Default to `false`. Only
+    /// applies with `core_dumps`.
+    pub fn crash_backtrace(mut self, crash_backtrace: bool) -> Self {
+        self.crash_backtrace = Some(crash_backtrace);
+        self
+    }
+
+    /// Resource limits of every task, see `ResourceLimits`. A param can override them with
+    /// `BuildCmd::build_limits`. Default to no limits.
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Memory, in bytes, the running tasks may need in total according to
+    /// `BuildCmd::build_resources`, see the `schedule` module. Default to the memory available
+    /// when each campaign starts.
+    pub fn memory_budget(mut self, memory_budget: u64) -> Self {
+        self.memory_budget = Some(memory_budget);
+        self
+    }
+
+    /// Assume a task whose param does not estimate its memory needs as much as the largest peak
+    /// RSS observed so far in its experiment. Default to `false`.
+    pub fn learn_memory(mut self, learn_memory: bool) -> Self {
+        self.learn_memory = Some(learn_memory);
+        self
+    }
+
+    /// Check the config file without building the executor.
+    ///
+    /// Every experiment is deserialized into `T`, and all the problems found are returned at
+    /// once, errors and warnings alike. An `Err` is only returned when the config file can not
+    /// be located or read, or when `T` has a field named after a reserved key.
+    pub fn validate<T: serde::de::DeserializeOwned>(&self) -> Result<Vec<ConfigDiagnostic>, Error> {
+        let config_format = self.config_format.clone().unwrap_or(ConfigFormat::Toml);
+        let config_path = self
+            .config_path
+            .clone()
+            .unwrap_or_else(|| default_config_path(&config_format));
+        let strictness = self.strictness.unwrap_or(Strictness::Warn);
+        let config_file_path = check_config_file(&config_path, &config_format)?;
+        let loaded = load_configs::<T>(&config_file_path, &config_format, strictness)?;
+        Ok(loaded.diagnostics)
+    }
+
     pub fn build<T: Default + BuildParam<P> + serde::de::DeserializeOwned, P: BuildCmd>(
         self,
-    ) -> Result<Executor<T, P>, Error> {
+    ) -> Result<Executor<T, P, H::Output>, Error>
+    where
+        H: PostProcess<P> + 'static,
+    {
         let config_format = self.config_format.unwrap_or(ConfigFormat::Toml);
-        let mut config_path = self.config_path.unwrap_or_else(|| match &config_format {
-            ConfigFormat::Ron => "config.ron".to_string(),
-            ConfigFormat::Toml => "config.toml".to_string(),
-            ConfigFormat::Json => "config.json".to_string(),
-            ConfigFormat::Yaml => "config.yaml".to_string(),
-        });
+        let mut config_path = self
+            .config_path
+            .unwrap_or_else(|| default_config_path(&config_format));
         let mut ns3_path = self.ns3_path.unwrap_or_else(|| "/".to_string());
         let task_concurrent = self.task_concurrent.unwrap_or_else(num_cpus::get);
         let retry_limit = self.retry_limit.unwrap_or(DEFAULT_RETRY_LIMIT as u32);
+        let launch_order = self.launch_order.unwrap_or(LaunchOrder::Sequential);
+        let strictness = self.strictness.unwrap_or(Strictness::Warn);
+        let dedup = self.dedup.unwrap_or(true);
+        let progress = self.progress.unwrap_or_else(default_reporter);
+        let keep_raw_output = self.keep_raw_output.unwrap_or(true);
+        let output_dir = match self.output_dir {
+            Some(output_dir) => Some(std::path::absolute(output_dir)?),
+            None => None,
+        };
+        let attribute_mode = self.attribute_mode.unwrap_or(AttributeMode::CommandLine);
+        if attribute_mode == AttributeMode::ConfigStore && output_dir.is_none() {
+            return Err(Error::InvalidConfig(
+                "AttributeMode::ConfigStore requires an output_dir.".to_string(),
+            ));
+        }
         // Check config file
         let config_file_path = check_config_file(&config_path, &config_format)?;
         config_path = config_file_path.display().to_string();
@@ -258,37 +1470,54 @@ impl ExecutorBuilder {
             }
         };
         ns3_path = ns3_dir_path.parent().unwrap().display().to_string();
-        let configs: HashMap<String, T> = match config_format {
-            ConfigFormat::Ron => {
-                let f = std::fs::File::open(config_file_path)?;
-                ron::de::from_reader(f)?
-            }
-            ConfigFormat::Json => {
-                let f = std::fs::File::open(config_file_path)?;
-                serde_json::from_reader(f)?
-            }
-            ConfigFormat::Yaml => {
-                let f = std::fs::File::open(config_file_path)?;
-                serde_yaml::from_reader(f)?
+        let manifest_path = match (self.manifest_path, &output_dir) {
+            (Some(manifest_path), _) => std::path::absolute(manifest_path)?,
+            (None, Some(output_dir)) => output_dir.join(MANIFEST_FILE),
+            (None, None) => Path::new(&ns3_path).join(timestamped_manifest_file()),
+        };
+        let loaded = load_configs::<T>(&config_file_path, &config_format, strictness)?;
+        let (errors, config_warnings): (Vec<_>, Vec<_>) = loaded
+            .diagnostics
+            .into_iter()
+            .partition(|d| d.severity == Severity::Error);
+        if !errors.is_empty() {
+            return Err(Error::InvalidConfigFormat(render_diagnostics(&errors)));
+        }
+        let configs = loaded.configs;
+        let config_envs = loaded.envs;
+        let metrics = self.metrics.unwrap_or_default();
+        let mut metric_extractors = IndexMap::new();
+        for (name, rules) in loaded.metrics {
+            let rules: Vec<MetricRule> = metrics.iter().cloned().chain(rules).collect();
+            let extractor = MetricExtractor::new(&rules).map_err(|e| {
+                Error::InvalidConfig(format!("Invalid metric rule of {}: {:?}.", name, e))
+            })?;
+            if !extractor.is_empty() {
+                metric_extractors.insert(name, extractor);
             }
-            ConfigFormat::Toml => {
-                let configuration = std::fs::read_to_string(config_file_path)?;
-                let configs: toml::value::Table = match toml::from_str(&configuration) {
-                    Ok(t) => t,
-                    Err(e) => {
-                        return Err(Error::InvalidConfigFormat(format!(
-                            "Config file is not a valid toml file. Err: {:?}.",
-                            e
-                        )));
-                    }
-                };
-                configs
-                    .iter()
-                    .map(|(k, v)| (k.to_owned(), v.to_owned().try_into().unwrap()))
-                    .collect()
+        }
+        let mut wrappers = loaded.wrappers;
+        if let Some(wrapper) = self.wrapper {
+            for name in configs.keys() {
+                wrappers
+                    .entry(name.to_owned())
+                    .or_insert_with(|| wrapper.clone());
             }
-        };
-        let outputs: HashMap<String, Vec<Task<P>>> =
+        }
+        wrappers.sort_by_cached_key(|name, _| configs.get_index_of(name));
+        let core_dumps = self.core_dumps.unwrap_or(false);
+        let crash_backtrace = self.crash_backtrace.unwrap_or(false);
+        if core_dumps && output_dir.is_none() {
+            return Err(Error::InvalidConfig(
+                "Core dumps require an output_dir.".to_string(),
+            ));
+        }
+        if (!wrappers.is_empty() || self.crash_wrapper.is_some()) && output_dir.is_none() {
+            return Err(Error::InvalidConfig(
+                "Wrappers require an output_dir.".to_string(),
+            ));
+        }
+        let outputs: IndexMap<String, Vec<Task<P, H::Output>>> =
             configs.keys().map(|k| (k.to_owned(), vec![])).collect();
 
         Ok(Executor {
@@ -297,13 +1526,46 @@ impl ExecutorBuilder {
             ns3_path,
             task_concurrent,
             retry_limit,
+            launch_order,
+            dedup,
+            task_timeout: self.task_timeout,
+            progress,
+            post_process: PostProcessor(Arc::new(self.post_process)),
+            keep_raw_output,
+            output_dir,
+            attribute_mode,
+            manifest_path,
+            pre_run: None,
+            sinks: vec![],
+            cache: None,
+            history: None,
+            subscribers: vec![],
+            config_warnings,
+            config_envs,
+            metric_extractors,
+            wrappers,
+            crash_wrapper: self.crash_wrapper,
+            core_dumps,
+            crash_backtrace,
+            limits: self.limits.unwrap_or_default(),
+            memory_budget: self.memory_budget,
+            learn_memory: self.learn_memory.unwrap_or(false),
             configs,
             outputs,
         })
     }
 }
 
-impl<P: BuildCmd> Task<P> {
+fn default_config_path(config_format: &ConfigFormat) -> String {
+    match config_format {
+        ConfigFormat::Ron => "config.ron".to_string(),
+        ConfigFormat::Toml => "config.toml".to_string(),
+        ConfigFormat::Json => "config.json".to_string(),
+        ConfigFormat::Yaml => "config.yaml".to_string(),
+    }
+}
+
+impl<P: BuildCmd, O> Task<P, O> {
     pub fn read_raw(&self) -> (Vec<u8>, Vec<u8>) {
         let stdout = self.output.stdout.clone();
         let stderr = self.output.stderr.clone();
@@ -329,25 +1591,196 @@ impl<P: BuildCmd> Task<P> {
     pub fn read_stderr(&self) -> &str {
         &self.stderr
     }
+
+    fn drop_raw_output(&mut self) {
+        self.output.stdout = vec![];
+        self.output.stderr = vec![];
+        self.stdout = String::new();
+        self.stderr = String::new();
+    }
+}
+
+impl<P: BuildCmd> Task<P> {
+    fn with_processed<O>(self, processed: O) -> Task<P, O> {
+        Task {
+            param: self.param,
+            index: self.index,
+            command: self.command,
+            work_dir: self.work_dir,
+            output: self.output,
+            stdout: self.stdout,
+            stderr: self.stderr,
+            cached: self.cached,
+            attempts: self.attempts,
+            elapsed: self.elapsed,
+            metrics: self.metrics,
+            reports: self.reports,
+            peak_rss: self.peak_rss,
+            processed,
+        }
+    }
 }
 
-async fn execute_ns3_program<P: BuildCmd>(
-    name: &str,
-    ns3_dir: impl AsRef<Path>,
-    param: P,
+/// Run `command` until it succeeds, at most `retry_limit` times, emitting the events of every
+/// task in `infos` along the way. `TaskSucceeded` is left to the caller, since processing the
+/// output may still fail the task.
+///
+/// With a `retry_limit` of 0 the command is run once, and its output is returned even when it
+/// failed. The attempts made and the time spent are returned along with the result, and the
+/// error comes with the output of the last attempt when it exited.
+///
+/// An attempt killed by a signal is handled according to `crash`: its core is collected and
+/// its backtrace taken, and when there is a template `command` is changed to run the program
+/// through it for the following attempts. A task whose last attempt crashed fails with
+/// `Error::Crashed`.
+///
+/// The processes are limited by `limits`, and an attempt exceeding them fails the task with
+/// `Error::LimitExceeded` at once, since retrying would exceed them again.
+async fn execute_ns3_program(
+    command: &mut TaskCommand,
+    infos: &[TaskInfo],
     retry_limit: u32,
-) -> Result<(&str, Task<P>), Error> {
-    let waf_path = ns3_dir.as_ref().join("waf");
-    let argument = param.build_cmd();
-    let mut cnt = 1;
-    let mut output = match Command::new(waf_path.as_os_str())
-        .arg("--run-no-build")
-        .arg(&argument)
-        .current_dir(&ns3_dir)
-        .output()
-        .await
-    {
-        Ok(output) => output,
+    task_timeout: Option<Duration>,
+    limits: &ResourceLimits,
+    crash: &CrashPolicy,
+    monitor: &Monitor<'_>,
+) -> (
+    Result<Output, (Error, Option<Output>)>,
+    u32,
+    Duration,
+    Option<u64>,
+) {
+    let start = Instant::now();
+    let mut attempt = 1;
+    let mut peak_rss = None;
+    loop {
+        monitor.emit_each(infos, |task| EventKind::TaskStarted { task, attempt });
+        let attempt_start = Instant::now();
+        let attempt_time = SystemTime::now();
+        let core_dumps = crash.core_dir.is_some();
+        let mut crashed = None;
+        let output = match run_once(command, task_timeout, limits, core_dumps).await {
+            Ok((output, peak)) => {
+                peak_rss = peak_rss.max(peak);
+                output
+            }
+            Err(e) => {
+                monitor.emit_each(infos, |task| EventKind::TaskFailed {
+                    task,
+                    attempts: attempt,
+                    elapsed: start.elapsed(),
+                    error: Some(e.clone()),
+                });
+                return (Err((e, None)), attempt, start.elapsed(), peak_rss);
+            }
+        };
+        match &output {
+            Some(output) if output.status.success() => {
+                return (Ok(output.clone()), attempt, start.elapsed(), peak_rss);
+            }
+            Some(output) => {
+                crashed = Crash::detect(output);
+                if let Some(crashed) = &mut crashed {
+                    let core_dumped = crashed.core_dumped != Some(false);
+                    if let Some(core_dir) = crash.core_dir.as_ref().filter(|_| core_dumped) {
+                        crashed.core = collect_core(core_dir, attempt_time, attempt);
+                    }
+                    if let Some(core) = crashed.core.as_ref().filter(|_| crash.backtrace) {
+                        crashed.backtrace =
+                            backtrace(crashed.program.as_deref(), core, attempt).await;
+                    }
+                    monitor.emit_each(infos, |task| EventKind::TaskCrashed {
+                        task,
+                        attempt,
+                        crash: crashed.clone(),
+                    });
+                }
+                if let Some(exceeded) = limits.exceeded(output, crashed.as_ref()) {
+                    let error = Error::LimitExceeded(exceeded);
+                    monitor.emit_each(infos, |task| EventKind::TaskFailed {
+                        task,
+                        attempts: attempt,
+                        elapsed: start.elapsed(),
+                        error: Some(error.clone()),
+                    });
+                    let output = Some(output.clone());
+                    return (Err((error, output)), attempt, start.elapsed(), peak_rss);
+                }
+                if let Some(template) = crash
+                    .template
+                    .as_ref()
+                    .filter(|_| crashed.is_some() && attempt < retry_limit)
+                {
+                    *command = command.clone().with_command_template(template);
+                }
+            }
+            None => monitor.emit_each(infos, |task| EventKind::TaskTimedOut {
+                task,
+                attempt,
+                elapsed: attempt_start.elapsed(),
+            }),
+        }
+        if attempt >= retry_limit {
+            let output = match output {
+                Some(output) if retry_limit == 0 => {
+                    return (Ok(output), attempt, start.elapsed(), peak_rss);
+                }
+                output => output,
+            };
+            let error = match crashed {
+                _ if output.is_none() && retry_limit == 0 => Error::ExecuteFail(format!(
+                    "NS3 program timed out after {:?}.",
+                    attempt_start.elapsed()
+                )),
+                Some(crashed) => Error::Crashed(crashed.to_string()),
+                None => Error::RetryLimitExceed,
+            };
+            monitor.emit_each(infos, |task| EventKind::TaskFailed {
+                task,
+                attempts: attempt,
+                elapsed: start.elapsed(),
+                error: Some(error.clone()),
+            });
+            return (Err((error, output)), attempt, start.elapsed(), peak_rss);
+        }
+        attempt += 1;
+        monitor.emit_each(infos, |task| EventKind::TaskRetried {
+            task,
+            attempt,
+            elapsed: start.elapsed(),
+        });
+    }
+}
+
+/// Run `command` once, returning its output and its peak RSS. `None` is returned instead of
+/// the output when it was killed for running longer than `task_timeout`.
+async fn run_once(
+    command: &TaskCommand,
+    task_timeout: Option<Duration>,
+    limits: &ResourceLimits,
+    core_dumps: bool,
+) -> Result<(Option<Output>, Option<u64>), Error> {
+    let mut child = command.to_command();
+    if core_dumps || !limits.is_empty() {
+        let limits = *limits;
+        // SAFETY: enable_core_dumps and ResourceLimits::apply only call async-signal-safe
+        // functions.
+        unsafe {
+            child.pre_exec(move || {
+                if core_dumps {
+                    enable_core_dumps()?;
+                }
+                limits.apply()
+            });
+        }
+    }
+    let child = child
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn();
+    let child = match child {
+        Ok(child) => child,
         Err(e) => {
             return Err(Error::ExecuteFail(format!(
                 "Failed to execute NS3 program. Err: {:?}.",
@@ -355,48 +1788,87 @@ async fn execute_ns3_program<P: BuildCmd>(
             )));
         }
     };
-    while !output.status.success() && cnt <= retry_limit {
-        cnt += 1;
-        if cnt > retry_limit {
-            return Err(Error::RetryLimitExceed);
-        }
-        output = match Command::new(waf_path.as_os_str())
-            .arg("--run-no-build")
-            .arg(&argument)
-            .current_dir(&ns3_dir)
-            .output()
-            .await
-        {
+    let pid = child.id();
+    // waf does not forward a kill to the program it launched, the whole group is killed instead.
+    let group = GroupGuard::new(pid);
+    let peak = Cell::new(None);
+    let mut wait = pin!(sample_peak_rss(child.wait_with_output(), pid, &peak));
+    let output = match task_timeout {
+        Some(t) => match timeout(t, &mut wait).await {
             Ok(output) => output,
-            Err(e) => {
-                return Err(Error::ExecuteFail(format!(
-                    "Failed to execute NS3 program. Err: {:?}.",
-                    e
-                )));
+            Err(_) => return Ok((None, peak.get())),
+        },
+        None => wait.await,
+    };
+    group.disarm();
+    match output {
+        Ok(output) => Ok((Some(output), peak.get())),
+        Err(e) => Err(Error::ExecuteFail(format!(
+            "Failed to execute NS3 program. Err: {:?}.",
+            e
+        ))),
+    }
+}
+
+/// Wait for `wait`, sampling the peak RSS of `pid` and its descendants into `peak` meanwhile.
+async fn sample_peak_rss<T>(
+    wait: impl Future<Output = T>,
+    pid: Option<u32>,
+    peak: &Cell<Option<u64>>,
+) -> T {
+    let mut wait = pin!(wait);
+    let mut interval = tokio::time::interval(PEAK_RSS_INTERVAL);
+    loop {
+        tokio::select! {
+            output = &mut wait => return output,
+            _ = interval.tick() => {
+                if let Some(sampled) = pid.map(peak_rss).filter(|&rss| rss > 0) {
+                    peak.set(peak.get().max(Some(sampled)));
+                }
             }
-        };
+        }
     }
+}
+
+/// Hand a copy of the output of a run to every param that requested it.
+fn fan_out<'a, P: BuildCmd>(
+    requesters: Vec<Requester<'a, P>>,
+    command: &TaskCommand,
+    output: Output,
+    (attempts, elapsed, peak_rss): (u32, Duration, Option<u64>),
+    work_dir: &Path,
+) -> Vec<Requester<'a, Task<P>>> {
     let stdout = String::from_utf8(output.stdout.clone()).unwrap();
     let stderr = String::from_utf8(output.stderr.clone()).unwrap();
-    Ok((
-        name,
-        Task {
-            param,
-            output,
-            stdout,
-            stderr,
-        },
-    ))
+    requesters
+        .into_iter()
+        .map(|(name, index, param)| {
+            (
+                name,
+                index,
+                Task {
+                    param,
+                    index,
+                    command: command.clone(),
+                    work_dir: work_dir.to_path_buf(),
+                    output: output.clone(),
+                    stdout: stdout.clone(),
+                    stderr: stderr.clone(),
+                    cached: false,
+                    attempts,
+                    elapsed,
+                    metrics: IndexMap::new(),
+                    reports: vec![],
+                    peak_rss,
+                    processed: (),
+                },
+            )
+        })
+        .collect()
 }
 
 async fn build_ns3_program(ns3_dir: impl AsRef<Path>) -> Result<(), Error> {
-    let waf_path = ns3_dir.as_ref().join("waf");
-    let output = match Command::new(waf_path.as_os_str())
-        .arg("build")
-        .current_dir(&ns3_dir)
-        .output()
-        .await
-    {
+    let output = match TaskCommand::build(ns3_dir).to_command().output().await {
         Ok(output) => output,
         Err(e) => {
             return Err(Error::ExecuteFail(format!(
@@ -414,3 +1886,353 @@ async fn build_ns3_program(ns3_dir: impl AsRef<Path>) -> Result<(), Error> {
         )))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::MetricMode;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[derive(Debug, Default, Deserialize)]
+    #[serde(default)]
+    struct TestConfig {
+        commands: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    struct TestParam {
+        command: String,
+    }
+
+    impl BuildParam<TestParam> for TestConfig {
+        fn build_param(&self) -> Vec<TestParam> {
+            self.commands
+                .iter()
+                .map(|c| TestParam { command: c.clone() })
+                .collect()
+        }
+    }
+
+    impl BuildCmd for TestParam {
+        fn build_cmd(&self) -> String {
+            self.command.clone()
+        }
+    }
+
+    /// A fresh directory holding a fake ns-3 tree, whose waf runs the argument of
+    /// `--run-no-build` as a shell command in a child process.
+    fn fake_ns3(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ns3-parallel-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let waf = dir.join("waf");
+        std::fs::write(
+            &waf,
+            "#!/bin/sh\n[ \"$1\" = build ] && exit 0\nsh -c \"$2\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&waf, std::fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    }
+
+    /// Builder of a single experiment `exp` running `commands` in the fake ns-3 tree `dir`.
+    fn builder(dir: &Path, commands: &[String]) -> ExecutorBuilder {
+        let config = dir.join("config.toml");
+        std::fs::write(&config, format!("[exp]\ncommands = {:?}\n", commands)).unwrap();
+        ExecutorBuilder::new()
+            .config_path(config.to_str().unwrap())
+            .ns3_path(dir.to_str().unwrap())
+            .task_concurrent(commands.len())
+            .progress(SilentReporter)
+    }
+
+    fn executor(dir: &Path, commands: &[String]) -> Executor<TestConfig, TestParam> {
+        builder(dir, commands).build().unwrap()
+    }
+
+    /// Whether `pid` is running, zombies excluded.
+    fn alive(pid: i32) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => !stat.rsplit(") ").next().unwrap_or("").starts_with('Z'),
+            Err(_) => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn dropping_the_stream_kills_running_tasks() {
+        let dir = fake_ns3("drop-stream");
+        let pid_files: Vec<PathBuf> = (0..2).map(|i| dir.join(format!("{}.pid", i))).collect();
+        let commands: Vec<String> = pid_files
+            .iter()
+            .map(|f| format!("echo $$ > {}; exec sleep 30", f.display()))
+            .collect();
+        let exe = executor(&dir, &commands);
+        let mut stream = Box::pin(exe.execute_stream().await.unwrap());
+        let started = Instant::now();
+        while !pid_files.iter().all(|f| f.exists()) {
+            assert!(started.elapsed() < Duration::from_secs(10));
+            let next = timeout(Duration::from_millis(100), stream.next()).await;
+            assert!(next.is_err(), "task finished before the stream was dropped");
+        }
+        // Let the shell write the pid before reading it.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let pids: Vec<i32> = pid_files
+            .iter()
+            .map(|f| std::fs::read_to_string(f).unwrap().trim().parse().unwrap())
+            .collect();
+        assert!(pids.iter().all(|&pid| alive(pid)));
+        drop(stream);
+        let dropped = Instant::now();
+        while pids.iter().any(|&pid| alive(pid)) {
+            assert!(
+                dropped.elapsed() < Duration::from_secs(5),
+                "task survived the stream"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    struct FailingSink;
+
+    impl ResultSink<TestParam> for FailingSink {
+        fn write(&mut self, _record: &TaskRecord<TestParam>) -> Result<(), Error> {
+            Err(Error::InvalidConfig("sink failed".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn a_task_failed_after_running_is_not_reported_as_succeeded() {
+        let dir = fake_ns3("failing-sink");
+        let mut exe = executor(&dir, &["true".to_string()]);
+        exe.add_sink(FailingSink);
+        let mut events = exe.subscribe();
+        let results: Vec<_> = exe.execute_stream().await.unwrap().collect().await;
+        assert!(results[0].is_err());
+        drop(exe);
+        let mut kinds = vec![];
+        while let Some(event) = events.recv().await {
+            kinds.push(event.kind);
+        }
+        assert!(!kinds
+            .iter()
+            .any(|k| matches!(k, EventKind::TaskSucceeded { .. })));
+        assert!(kinds.iter().any(|k| matches!(
+            k,
+            EventKind::TaskFailed {
+                attempts: 1,
+                error: Some(Error::InvalidConfig(_)),
+                ..
+            }
+        )));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Exit code, stdout and stderr of a record.
+    type RecordedOutput = (Option<i32>, String, String);
+
+    /// The outputs of every record written.
+    #[derive(Clone, Default)]
+    struct OutputSink(Arc<Mutex<Vec<RecordedOutput>>>);
+
+    impl ResultSink<TestParam> for OutputSink {
+        fn write(&mut self, record: &TaskRecord<TestParam>) -> Result<(), Error> {
+            let output = (
+                record.exit_code,
+                record.stdout.to_string(),
+                record.stderr.to_string(),
+            );
+            self.0.lock().unwrap().push(output);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_tasks_are_recorded_with_their_last_output() {
+        let dir = fake_ns3("failed-output");
+        let mut exe: Executor<TestConfig, TestParam> =
+            builder(&dir, &["echo out; echo err >&2; exit 3".to_string()])
+                .retry_limit(2)
+                .build()
+                .unwrap();
+        let sink = OutputSink::default();
+        exe.add_sink(sink.clone());
+        let results: Vec<_> = exe.execute_stream().await.unwrap().collect().await;
+        assert!(matches!(
+            results[0],
+            Err(TaskError {
+                error: Error::RetryLimitExceed,
+                ..
+            })
+        ));
+        let records = sink.0.lock().unwrap();
+        assert_eq!(
+            *records,
+            [(Some(3), "out\n".to_string(), "err\n".to_string())]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn identical_params_in_their_own_directories_run_once() {
+        let dir = fake_ns3("dedup");
+        let command = "echo run >> runs; echo out".to_string();
+        let mut exe: Executor<TestConfig, TestParam> = builder(&dir, &[command.clone(), command])
+            .output_dir(dir.join("out").to_str().unwrap())
+            .build()
+            .unwrap();
+        exe.execute().await.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("runs")).unwrap(), "run\n");
+        let tasks = &exe.get_outputs()["exp"];
+        assert_eq!(tasks.len(), 2);
+        let first = dir.join("out").join("exp").join("0");
+        for task in tasks {
+            assert_eq!(task.stdout, "out\n");
+            assert_eq!(task.work_dir, first);
+        }
+        assert!(!dir.join("out").join("exp").join("1").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn columns_dropped_by_sinks_are_emitted() {
+        let dir = fake_ns3("dropped-columns");
+        let commands = ["echo a=1".to_string(), "echo a=2 b=3".to_string()];
+        let mut exe: Executor<TestConfig, TestParam> = builder(&dir, &commands)
+            .task_concurrent(1)
+            .metrics(vec![MetricRule::new(MetricMode::KeyValue)])
+            .build()
+            .unwrap();
+        exe.add_sink(crate::export::CsvSink::new(vec![]));
+        let mut events = exe.subscribe();
+        exe.execute().await.unwrap();
+        drop(exe);
+        let mut dropped = vec![];
+        while let Some(event) = events.recv().await {
+            if let EventKind::ColumnsDropped { task, columns } = event.kind {
+                dropped.push((task.index, columns));
+            }
+        }
+        assert_eq!(dropped, [(1, vec!["metric.b".to_string()])]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn post_process_runs_off_the_runtime_thread() {
+        let dir = fake_ns3("post-process");
+        let exe: Executor<TestConfig, TestParam, std::thread::ThreadId> =
+            builder(&dir, &["true".to_string()])
+                .post_process(|_: &Task<TestParam>| Ok(std::thread::current().id()))
+                .build()
+                .unwrap();
+        let results: Vec<_> = exe.execute_stream().await.unwrap().collect().await;
+        let (_, task) = results[0].as_ref().unwrap();
+        assert_ne!(task.processed, std::thread::current().id());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Counts the ticks received before the first task finished.
+    #[derive(Debug, Default)]
+    struct TickCounter {
+        ticks: Arc<AtomicUsize>,
+        finished: AtomicBool,
+    }
+
+    impl ProgressReporter for TickCounter {
+        fn task_finished(&self, _experiment: &str, _success: bool) {
+            self.finished.store(true, Ordering::SeqCst);
+        }
+
+        fn tick(&self) {
+            if !self.finished.load(Ordering::SeqCst) {
+                self.ticks.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_is_ticked_while_tasks_run() {
+        let dir = fake_ns3("progress-tick");
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let exe: Executor<TestConfig, TestParam> = builder(&dir, &["sleep 2.5".to_string()])
+            .progress(TickCounter {
+                ticks: ticks.clone(),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let results: Vec<_> = exe.execute_stream().await.unwrap().collect().await;
+        assert!(results[0].is_ok());
+        assert!(ticks.load(Ordering::SeqCst) >= 3);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn the_manifest_defaults_to_a_timestamped_file_in_the_ns3_directory() {
+        let dir = fake_ns3("manifest");
+        let exe = executor(&dir, &["true".to_string()]);
+        let manifest_path = exe.get_manifest_path().to_path_buf();
+        assert_eq!(manifest_path.parent(), Some(dir.as_path()));
+        let name = manifest_path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("manifest-") && name.ends_with(".json"));
+        std::thread::sleep(Duration::from_millis(2));
+        let concurrent = executor(&dir, &["true".to_string()]);
+        assert_ne!(concurrent.get_manifest_path(), manifest_path);
+        let results: Vec<_> = exe.execute_stream().await.unwrap().collect().await;
+        assert!(results[0].is_ok());
+        let manifest = Manifest::from_file(&manifest_path).unwrap();
+        assert_eq!(manifest.tasks.len(), 1);
+        assert_eq!(manifest.tasks[0].experiment, "exp");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Runtimes of the commands running `a` to `d`: `b` never ran and `d` cannot be read.
+    struct FixedHistory;
+
+    impl RuntimeHistory for FixedHistory {
+        fn runtime(
+            &mut self,
+            _experiment: &str,
+            command: &TaskCommand,
+        ) -> Result<Option<Duration>, Error> {
+            match command.args[1].as_str() {
+                "a" => Ok(Some(Duration::from_secs(1))),
+                "c" => Ok(Some(Duration::from_secs(5))),
+                "d" => Err(Error::IoError("unreadable history".to_string())),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn longest_tasks_are_launched_first() {
+        let dir = fake_ns3("longest-first");
+        let commands: Vec<String> = ["a", "b", "c", "d"].iter().map(|c| c.to_string()).collect();
+        let mut exe: Executor<TestConfig, TestParam> = builder(&dir, &commands)
+            .launch_order(LaunchOrder::LongestFirst)
+            .build()
+            .unwrap();
+        exe.estimate_from(FixedHistory);
+        // Unestimated tasks run as long as the mean of the others, and keep their order.
+        let queue: Vec<_> = exe
+            .queue()
+            .into_iter()
+            .map(|task| (task.requester.2.command, task.estimate.map(|e| e.as_secs())))
+            .collect();
+        assert_eq!(
+            queue,
+            [
+                ("c".to_string(), Some(5)),
+                ("b".to_string(), Some(3)),
+                ("d".to_string(), Some(3)),
+                ("a".to_string(), Some(1)),
+            ]
+        );
+
+        let exe = executor(&dir, &commands);
+        let queue: Vec<_> = exe.queue().into_iter().map(|t| t.estimate).collect();
+        assert_eq!(queue, [None; 4]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,560 @@
+//! Export of task results
+//!
+//! Sinks registered with `Executor::add_sink` receive a `TaskRecord` for every task as soon as
+//! it finishes, succeeded or failed. `CsvSink` and `JsonLinesSink` write each record as one flat
+//! row: the metadata columns (`experiment`, `index`, `status`, `cached`, `attempts`,
+//! `elapsed`, `exit_code`, `error`, `command`), then the fields of the serialized param prefixed
+//! with `param.`, then the metrics prefixed with `metric.`. Nested fields are joined with `.`.
+//!
+//! With the `sqlite` feature, `sqlite::SqliteStore` keeps the records of every campaign in a
+//! SQLite database, which can also serve as a `ResultCache` and as a `RuntimeHistory`. With the `arrow` feature,
+//! `arrow::ParquetSink` writes them to a Parquet file in bounded batches.
+//!
+//! ## Example
+//!
+//! ```
+//! use indexmap::IndexMap;
+//! use ns3_parallel::export::{CsvSink, ResultSink, TaskRecord};
+//! use ns3_parallel::plan::TaskCommand;
+//! use serde::Serialize;
+//! use std::time::Duration;
+//!
+//! #[derive(Serialize)]
+//! struct MyParam {
+//!     policy: u32,
+//! }
+//!
+//! let metrics = IndexMap::from([("throughput".to_string(), 12.5)]);
+//! let record = TaskRecord {
+//!     experiment: "exp-1",
+//!     index: 0,
+//!     param: &MyParam { policy: 1 },
+//!     command: &TaskCommand::run("/ns-3", "simple-ns3 --policy=1".to_string()),
+//!     cached: false,
+//!     attempts: 1,
+//!     elapsed: Duration::from_millis(1500),
+//!     exit_code: Some(0),
+//!     metrics: &metrics,
+//!     stdout: "throughput=12.5 Mbps",
+//!     stderr: "",
+//!     error: None,
+//! };
+//! let mut sink = CsvSink::new(vec![]);
+//! sink.write(&record).unwrap();
+//! let csv = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+//! assert!(csv.starts_with("experiment,index,status,cached,attempts,elapsed,exit_code,error,"));
+//! assert!(csv.contains("param.policy,metric.throughput\n"));
+//! assert!(csv.ends_with(",1,12.5\n"));
+//! ```
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::manifest::Manifest;
+use crate::plan::TaskCommand;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// # TaskRecord
+///
+/// What a `ResultSink` receives of a finished task.
+#[derive(Debug)]
+pub struct TaskRecord<'a, P> {
+    pub experiment: &'a str,
+    pub index: usize,
+    pub param: &'a P,
+    pub command: &'a TaskCommand,
+    /// Whether the output was taken from the `ResultCache` instead of running the command.
+    pub cached: bool,
+    /// Attempts made, 0 when the task failed before being launched.
+    pub attempts: u32,
+    /// Time spent running the task, retries included.
+    pub elapsed: Duration,
+    /// `None` when the program did not exit, e.g. it timed out or was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// Metrics extracted from the output, empty when the task failed.
+    pub metrics: &'a IndexMap<String, f64>,
+    /// Output of the program, of its last attempt when the task failed, empty when that
+    /// attempt did not exit. Not part of the rows.
+    pub stdout: &'a str,
+    pub stderr: &'a str,
+    /// `None` when the task succeeded.
+    pub error: Option<&'a Error>,
+}
+
+impl<P: Serialize> TaskRecord<'_, P> {
+    /// The record as one flat row, see the module documentation for its columns.
+    pub fn to_row(&self) -> Result<IndexMap<String, Value>, Error> {
+        let mut row = IndexMap::new();
+        row.insert("experiment".to_string(), Value::from(self.experiment));
+        row.insert("index".to_string(), Value::from(self.index));
+        let status = match self.error {
+            None => "succeeded",
+            Some(_) => "failed",
+        };
+        row.insert("status".to_string(), Value::from(status));
+        row.insert("cached".to_string(), Value::from(self.cached));
+        row.insert("attempts".to_string(), Value::from(self.attempts));
+        row.insert(
+            "elapsed".to_string(),
+            Value::from(self.elapsed.as_secs_f64()),
+        );
+        row.insert("exit_code".to_string(), Value::from(self.exit_code));
+        row.insert(
+            "error".to_string(),
+            Value::from(self.error.map(|e| format!("{:?}", e))),
+        );
+        row.insert("command".to_string(), Value::from(self.command.to_string()));
+        flatten("param", serde_json::to_value(self.param)?, &mut row);
+        for (name, value) in self.metrics {
+            row.insert(format!("metric.{}", name), Value::from(*value));
+        }
+        Ok(row)
+    }
+}
+
+pub(crate) fn flatten(prefix: &str, value: Value, row: &mut IndexMap<String, Value>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten(&format!("{}.{}", prefix, key), value, row);
+            }
+        }
+        value => {
+            row.insert(prefix.to_string(), value);
+        }
+    }
+}
+
+/// # ResultSink
+///
+/// Destination of the records of the tasks, registered with `Executor::add_sink`.
+///
+/// `write` is called once for every task, in the order tasks finish, and `finish` once all of
+/// them are done. An `Err` returned by `write` for a succeeded task fails it.
+pub trait ResultSink<P>: Send {
+    /// Called once the manifest of a campaign is written, before any of its tasks finishes.
+    fn start(&mut self, _manifest: &Manifest) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn write(&mut self, record: &TaskRecord<P>) -> Result<(), Error>;
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Columns of the records written so far that the sink left out, in the order they showed
+    /// up. The executor emits `EventKind::ColumnsDropped` when new ones show up.
+    fn dropped_columns(&self) -> &[String] {
+        &[]
+    }
+}
+
+/// # CachedTask
+///
+/// Output of an earlier successful run of a command, served by a `ResultCache`.
+#[derive(Debug, Clone)]
+pub struct CachedTask {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_code: i32,
+    pub attempts: u32,
+    pub elapsed: Duration,
+}
+
+/// # ResultCache
+///
+/// Source of the outputs of commands that already ran, set with `Executor::resume_from`.
+///
+/// The executor looks each command up right before launching it, once the pre-run hook was
+/// called, and uses the cached output instead of running the command when there is one.
+pub trait ResultCache: Send {
+    /// The output of the latest successful run of `command` at `revision`, if any. Nothing
+    /// should be served for a revision that is not clean, see `Revision::is_clean`.
+    fn lookup(
+        &mut self,
+        command: &TaskCommand,
+        revision: &Revision,
+    ) -> Result<Option<CachedTask>, Error>;
+}
+
+/// # Revision
+///
+/// What the output of a command depends on besides the command line: the ns-3 sources and the
+/// config file, as recorded in the `Manifest` of the campaign.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revision {
+    pub git_commit: Option<String>,
+    pub git_dirty: Option<bool>,
+    pub config_sha256: String,
+}
+
+impl Revision {
+    pub fn of(manifest: &Manifest) -> Self {
+        Revision {
+            git_commit: manifest.ns3.git_commit.clone(),
+            git_dirty: manifest.ns3.git_dirty,
+            config_sha256: manifest.config_sha256.clone(),
+        }
+    }
+
+    /// Whether the ns-3 sources are known exactly: the tree is a git repository without local
+    /// modifications to tracked files.
+    pub fn is_clean(&self) -> bool {
+        self.git_commit.is_some() && self.git_dirty == Some(false)
+    }
+}
+
+/// # RuntimeHistory
+///
+/// Runtimes of tasks of earlier campaigns, set with `Executor::estimate_from` to estimate how
+/// long the tasks whose param does not state it will run, see `LaunchOrder::LongestFirst`.
+pub trait RuntimeHistory: Send {
+    /// The expected runtime of `command`, run for a task of `experiment`, if anything similar
+    /// ran before.
+    fn runtime(
+        &mut self,
+        experiment: &str,
+        command: &TaskCommand,
+    ) -> Result<Option<Duration>, Error>;
+}
+
+/// The cache held by an executor.
+#[derive(Clone)]
+pub(crate) struct CacheHandle(pub Arc<Mutex<dyn ResultCache>>);
+
+impl fmt::Debug for CacheHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CacheHandle")
+    }
+}
+
+/// The runtime history held by an executor.
+#[derive(Clone)]
+pub(crate) struct HistoryHandle(pub Arc<Mutex<dyn RuntimeHistory>>);
+
+impl fmt::Debug for HistoryHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("HistoryHandle")
+    }
+}
+
+/// The sinks held by an executor.
+pub(crate) struct SinkHandle<P>(pub Arc<Mutex<dyn ResultSink<P>>>);
+
+impl<P> Clone for SinkHandle<P> {
+    fn clone(&self) -> Self {
+        SinkHandle(self.0.clone())
+    }
+}
+
+impl<P> fmt::Debug for SinkHandle<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SinkHandle")
+    }
+}
+
+/// # CsvSink
+///
+/// Write records as CSV rows, flushed one by one.
+///
+/// The header is written with the first succeeded record, so it holds the metric columns.
+/// Failed records arriving before it are kept until then, or until `finish`. Columns never
+/// change afterwards: a missing field is left empty, and the columns of a record that are not
+/// in the header, such as an optional metric, are written along with the experiment and the
+/// index of the record as a JSON line to the overflow writer, and reported by
+/// `ResultSink::dropped_columns`. A sink made with `create` overflows to
+/// `<name>.overflow.jsonl` next to the CSV file, one made with `new` only once `overflow` is
+/// set, and drops these columns otherwise.
+pub struct CsvSink<W: Write> {
+    writer: csv::Writer<W>,
+    columns: Option<Vec<String>>,
+    pending: Vec<IndexMap<String, Value>>,
+    overflow: Option<Overflow>,
+    dropped: Vec<String>,
+}
+
+/// Where the columns missing from the CSV header go, opened when first needed.
+enum Overflow {
+    Path(PathBuf),
+    Writer(Box<dyn Write + Send>),
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let sink = Self::new(BufWriter::new(File::create(path)?));
+        Ok(CsvSink {
+            overflow: Some(Overflow::Path(path.with_extension("overflow.jsonl"))),
+            ..sink
+        })
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        CsvSink {
+            writer: csv::WriterBuilder::new().flexible(true).from_writer(writer),
+            columns: None,
+            pending: vec![],
+            overflow: None,
+            dropped: vec![],
+        }
+    }
+
+    /// Write the columns of records that are not in the header to `writer`, as JSON Lines.
+    pub fn overflow(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.overflow = Some(Overflow::Writer(Box::new(writer)));
+        self
+    }
+
+    /// Write the pending records and return the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, Error> {
+        self.write_pending()?;
+        self.writer
+            .into_inner()
+            .map_err(|e| Error::IoError(format!("{:?}", e.error())))
+    }
+
+    fn write_header(&mut self, first: &IndexMap<String, Value>) -> Result<(), Error> {
+        let mut columns: Vec<String> = first.keys().cloned().collect();
+        for row in &self.pending {
+            for key in row.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.to_owned());
+                }
+            }
+        }
+        self.writer.write_record(&columns).map_err(csv_error)?;
+        self.columns = Some(columns);
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &IndexMap<String, Value>) -> Result<(), Error> {
+        let columns = self.columns.as_deref().unwrap_or_default();
+        let fields: Vec<String> = columns.iter().map(|c| cell(row.get(c))).collect();
+        let extra: IndexMap<&String, &Value> =
+            row.iter().filter(|(k, _)| !columns.contains(k)).collect();
+        self.writer.write_record(&fields).map_err(csv_error)?;
+        if !extra.is_empty() {
+            self.write_overflow(row, extra)?;
+        }
+        Ok(())
+    }
+
+    fn write_overflow(
+        &mut self,
+        row: &IndexMap<String, Value>,
+        extra: IndexMap<&String, &Value>,
+    ) -> Result<(), Error> {
+        if let Some(Overflow::Path(path)) = &self.overflow {
+            let file = BufWriter::new(File::create(path)?);
+            self.overflow = Some(Overflow::Writer(Box::new(file)));
+        }
+        for column in extra.keys() {
+            if !self.dropped.contains(column) {
+                self.dropped.push(column.to_string());
+            }
+        }
+        let Some(Overflow::Writer(writer)) = &mut self.overflow else {
+            return Ok(());
+        };
+        let mut line = IndexMap::new();
+        for key in ["experiment", "index"] {
+            if let Some(value) = row.get(key) {
+                line.insert(key, value);
+            }
+        }
+        line.extend(extra.into_iter().map(|(k, v)| (k.as_str(), v)));
+        serde_json::to_writer(&mut *writer, &line)?;
+        writer.write_all(b"\n")?;
+        Ok(writer.flush()?)
+    }
+
+    fn write_pending(&mut self) -> Result<(), Error> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        if self.columns.is_none() {
+            let first = self.pending[0].clone();
+            self.write_header(&first)?;
+        }
+        // Rows are only dropped once written, so a failed write can be retried.
+        while let Some(row) = self.pending.first().cloned() {
+            self.write_row(&row)?;
+            self.pending.remove(0);
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<P: Serialize, W: Write + Send> ResultSink<P> for CsvSink<W> {
+    fn write(&mut self, record: &TaskRecord<P>) -> Result<(), Error> {
+        let row = record.to_row()?;
+        if self.columns.is_none() {
+            if record.error.is_some() {
+                self.pending.push(row);
+                return Ok(());
+            }
+            self.write_header(&row)?;
+            self.write_pending()?;
+        }
+        self.write_row(&row)?;
+        Ok(self.writer.flush()?)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.write_pending()?;
+        if let Some(Overflow::Writer(writer)) = &mut self.overflow {
+            writer.flush()?;
+        }
+        Ok(self.writer.flush()?)
+    }
+
+    fn dropped_columns(&self) -> &[String] {
+        &self.dropped
+    }
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.to_owned(),
+        Some(value) => value.to_string(),
+    }
+}
+
+fn csv_error(e: csv::Error) -> Error {
+    Error::IoError(format!("{:?}", e))
+}
+
+/// # JsonLinesSink
+///
+/// Write records as JSON objects, one per line, flushed one by one.
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+}
+
+impl JsonLinesSink<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesSink { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<P: Serialize, W: Write + Send> ResultSink<P> for JsonLinesSink<W> {
+    fn write(&mut self, record: &TaskRecord<P>) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.writer, &record.to_row()?)?;
+        self.writer.write_all(b"\n")?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestParam {
+        policy: u32,
+    }
+
+    /// A writer whose content stays readable once it was moved into a sink.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn write(
+        sink: &mut impl ResultSink<TestParam>,
+        index: usize,
+        metrics: &[(&str, f64)],
+        error: Option<&Error>,
+    ) -> Result<(), Error> {
+        let metrics: IndexMap<String, f64> =
+            metrics.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        sink.write(&TaskRecord {
+            experiment: "exp",
+            index,
+            param: &TestParam { policy: 1 },
+            command: &TaskCommand::run("/ns-3", "simple-ns3".to_string()),
+            cached: false,
+            attempts: 1,
+            elapsed: Duration::from_secs(1),
+            exit_code: Some(0),
+            metrics: &metrics,
+            stdout: "",
+            stderr: "",
+            error,
+        })
+    }
+
+    #[test]
+    fn csv_columns_missing_from_the_header_overflow() {
+        let overflow = SharedBuf::default();
+        let mut sink = CsvSink::new(vec![]).overflow(overflow.clone());
+        write(&mut sink, 0, &[("throughput", 1.0)], None).unwrap();
+        write(&mut sink, 1, &[("throughput", 2.0), ("delay", 3.0)], None).unwrap();
+        assert_eq!(
+            ResultSink::<TestParam>::dropped_columns(&sink),
+            ["metric.delay"]
+        );
+        let csv = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().all(|l| !l.contains("delay")));
+        assert_eq!(
+            overflow.text(),
+            "{\"experiment\":\"exp\",\"index\":1,\"metric.delay\":3.0}\n"
+        );
+    }
+
+    #[test]
+    fn csv_failed_records_before_the_header_are_all_written() {
+        let failed = Error::RetryLimitExceed;
+        let mut sink = CsvSink::new(vec![]);
+        write(&mut sink, 0, &[], Some(&failed)).unwrap();
+        write(&mut sink, 1, &[], Some(&failed)).unwrap();
+        write(&mut sink, 2, &[("throughput", 1.0)], None).unwrap();
+        let csv = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].ends_with(",param.policy,metric.throughput"));
+        assert!(lines[1].starts_with("exp,0,failed,"));
+        assert!(lines[3].starts_with("exp,2,succeeded,"));
+    }
+}
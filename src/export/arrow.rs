@@ -0,0 +1,445 @@
+//! Arrow and Parquet export
+//!
+//! `BatchBuilder` gathers the rows of `TaskRecord::to_row` into Arrow record batches of at most
+//! `batch_size` rows, so memory stays bounded however many tasks run. `ParquetSink` writes each
+//! batch as a row group of a Parquet file as soon as it is full.
+//!
+//! Metric columns are `Float64`. The types of the other columns are inferred from the first
+//! batch: `Boolean`, `Int64` or `Float64` when every value of the column is one, `Utf8`
+//! otherwise, or `Utf8` when the column has no value. Like `CsvSink`, the first batch waits for a succeeded row so
+//! it holds the metric columns, and columns never change afterwards: a missing value is null,
+//! and a new column or a value that does not fit the type of its column is left out rather than
+//! failing the task, see `BatchBuilder::dropped_columns`.
+//!
+//! ## Example
+//!
+//! ```
+//! use indexmap::IndexMap;
+//! use ns3_parallel::export::arrow::BatchBuilder;
+//! use serde_json::Value;
+//!
+//! let mut builder = BatchBuilder::new(2);
+//! for policy in 0..3 {
+//!     let row = IndexMap::from([
+//!         ("param.policy".to_string(), Value::from(policy)),
+//!         ("metric.throughput".to_string(), Value::from(12.5)),
+//!     ]);
+//!     if let Some(batch) = builder.push(row, true).unwrap() {
+//!         assert_eq!(batch.num_rows(), 2);
+//!     }
+//! }
+//! let last = builder.flush().unwrap().unwrap();
+//! assert_eq!(last.num_rows(), 1);
+//! assert_eq!(last.schema().field(0).data_type(), &arrow::datatypes::DataType::Int64);
+//! ```
+
+use ::arrow::array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray};
+use ::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use ::arrow::record_batch::RecordBatch;
+use ::parquet::arrow::ArrowWriter;
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::export::{ResultSink, TaskRecord};
+
+/// Rows kept in memory by `ParquetSink` before they are written. Default of `batch_size`.
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// # BatchBuilder
+///
+/// Gather rows into Arrow record batches, see the module documentation.
+#[derive(Debug)]
+pub struct BatchBuilder {
+    batch_size: usize,
+    schema: Option<SchemaRef>,
+    rows: Vec<IndexMap<String, Value>>,
+    succeeded: bool,
+    dropped: Vec<String>,
+}
+
+impl BatchBuilder {
+    pub fn new(batch_size: usize) -> Self {
+        BatchBuilder {
+            batch_size: batch_size.max(1),
+            schema: None,
+            rows: vec![],
+            succeeded: false,
+            dropped: vec![],
+        }
+    }
+
+    /// Columns with a value left out of the batches, for not being in the schema or not fitting
+    /// the type of their column, in the order they showed up.
+    pub fn dropped_columns(&self) -> &[String] {
+        &self.dropped
+    }
+
+    /// Schema of the batches, known once the first batch was built.
+    pub fn schema(&self) -> Option<SchemaRef> {
+        self.schema.clone()
+    }
+
+    /// Add a row, `succeeded` telling whether its task succeeded, and return a batch when
+    /// there are `batch_size` rows.
+    pub fn push(
+        &mut self,
+        row: IndexMap<String, Value>,
+        succeeded: bool,
+    ) -> Result<Option<RecordBatch>, Error> {
+        self.rows.push(row);
+        self.succeeded |= succeeded;
+        if self.rows.len() < self.batch_size || (self.schema.is_none() && !self.succeeded) {
+            return Ok(None);
+        }
+        self.flush()
+    }
+
+    /// Return the rows left as a batch, if any. The rows are kept when it fails.
+    pub fn flush(&mut self) -> Result<Option<RecordBatch>, Error> {
+        if self.rows.is_empty() {
+            return Ok(None);
+        }
+        let schema = match &self.schema {
+            Some(schema) => schema.clone(),
+            None => infer_schema(&self.rows),
+        };
+        for (key, value) in self.rows.iter().flat_map(|row| row.iter()) {
+            let fits = match schema.field_with_name(key) {
+                Ok(field) => fits(field.data_type(), value),
+                Err(_) => false,
+            };
+            if !fits && !self.dropped.contains(key) {
+                self.dropped.push(key.to_owned());
+            }
+        }
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| build_column(field, &self.rows))
+            .collect();
+        let batch = RecordBatch::try_new(schema.clone(), columns).map_err(arrow_error)?;
+        self.schema = Some(schema);
+        self.rows.clear();
+        Ok(Some(batch))
+    }
+}
+
+fn infer_schema(rows: &[IndexMap<String, Value>]) -> SchemaRef {
+    let mut names: Vec<&String> = vec![];
+    for key in rows.iter().flat_map(|row| row.keys()) {
+        if !names.contains(&key) {
+            names.push(key);
+        }
+    }
+    let fields: Vec<Field> = names
+        .into_iter()
+        .map(|name| {
+            let values = rows.iter().filter_map(|row| row.get(name));
+            Field::new(name, infer_type(name, values), true)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn infer_type<'a>(name: &str, values: impl Iterator<Item = &'a Value>) -> DataType {
+    // A metric whose first values happen to be whole numbers may take any value later.
+    if name.starts_with("metric.") {
+        return DataType::Float64;
+    }
+    let mut inferred: Option<DataType> = None;
+    for value in values {
+        let data_type = match value {
+            Value::Null => continue,
+            Value::Bool(_) => DataType::Boolean,
+            Value::Number(n) if n.is_i64() => DataType::Int64,
+            Value::Number(_) => DataType::Float64,
+            _ => return DataType::Utf8,
+        };
+        inferred = Some(match (inferred, data_type) {
+            (None, data_type) => data_type,
+            (Some(a), b) if a == b => a,
+            (Some(DataType::Int64 | DataType::Float64), DataType::Int64 | DataType::Float64) => {
+                DataType::Float64
+            }
+            _ => return DataType::Utf8,
+        });
+    }
+    inferred.unwrap_or(DataType::Utf8)
+}
+
+/// Whether `value` can be stored in a column of `data_type`, nulls included.
+fn fits(data_type: &DataType, value: &Value) -> bool {
+    match data_type {
+        _ if value.is_null() => true,
+        DataType::Boolean => value.is_boolean(),
+        DataType::Int64 => value.is_i64(),
+        DataType::Float64 => value.is_number(),
+        _ => true,
+    }
+}
+
+/// Column `field` of `rows`, with null for the values that do not fit its type.
+fn build_column(field: &Field, rows: &[IndexMap<String, Value>]) -> ArrayRef {
+    let name = field.name();
+    let values = rows.iter().map(|row| {
+        row.get(name)
+            .filter(|v| !v.is_null() && fits(field.data_type(), v))
+    });
+    match field.data_type() {
+        DataType::Boolean => Arc::new(values.map(|v| v?.as_bool()).collect::<BooleanArray>()),
+        DataType::Int64 => Arc::new(values.map(|v| v?.as_i64()).collect::<Int64Array>()),
+        DataType::Float64 => Arc::new(values.map(|v| v?.as_f64()).collect::<Float64Array>()),
+        _ => Arc::new(
+            values
+                .map(|v| {
+                    v.map(|v| match v {
+                        Value::String(s) => s.to_owned(),
+                        v => v.to_string(),
+                    })
+                })
+                .collect::<StringArray>(),
+        ),
+    }
+}
+
+fn arrow_error(e: ::arrow::error::ArrowError) -> Error {
+    Error::IoError(format!("{:?}", e))
+}
+
+fn parquet_error(e: ::parquet::errors::ParquetError) -> Error {
+    Error::IoError(format!("{:?}", e))
+}
+
+enum State<W: Write + Send> {
+    Pending(W),
+    Writing(Box<ArrowWriter<W>>),
+    Finished(W),
+    /// The writer was lost to an error.
+    Closed,
+}
+
+/// # ParquetSink
+///
+/// Write records to a Parquet file, one row group per batch of `batch_size` rows.
+///
+/// The file is complete once `ResultSink::finish` was called, which `Executor::execute` does
+/// at the end of the campaign. A sink holds a single campaign: records written after `finish`
+/// are an error.
+pub struct ParquetSink<W: Write + Send> {
+    state: State<W>,
+    builder: BatchBuilder,
+}
+
+impl ParquetSink<File> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Send> ParquetSink<W> {
+    pub fn new(writer: W) -> Self {
+        ParquetSink {
+            state: State::Pending(writer),
+            builder: BatchBuilder::new(DEFAULT_BATCH_SIZE),
+        }
+    }
+
+    /// Finish the file and return the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, Error> {
+        self.finish_file()?;
+        match self.state {
+            State::Finished(writer) => Ok(writer),
+            _ => Err(finished()),
+        }
+    }
+
+    /// Rows in each row group. Default to `DEFAULT_BATCH_SIZE`.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.builder.batch_size = batch_size.max(1);
+        self
+    }
+
+    fn write_batch(&mut self, batch: RecordBatch) -> Result<(), Error> {
+        if let State::Pending(_) = self.state {
+            let State::Pending(writer) = std::mem::replace(&mut self.state, State::Closed) else {
+                unreachable!()
+            };
+            let writer =
+                ArrowWriter::try_new(writer, batch.schema(), None).map_err(parquet_error)?;
+            self.state = State::Writing(Box::new(writer));
+        }
+        match &mut self.state {
+            State::Writing(writer) => {
+                writer.write(&batch).map_err(parquet_error)?;
+                writer.flush().map_err(parquet_error)
+            }
+            _ => Err(finished()),
+        }
+    }
+
+    fn finish_file(&mut self) -> Result<(), Error> {
+        if let Some(batch) = self.builder.flush()? {
+            self.write_batch(batch)?;
+        }
+        self.state = match std::mem::replace(&mut self.state, State::Closed) {
+            State::Pending(writer) => State::Finished(writer),
+            State::Writing(writer) => State::Finished(writer.into_inner().map_err(parquet_error)?),
+            state => state,
+        };
+        Ok(())
+    }
+}
+
+fn finished() -> Error {
+    Error::InvalidConfig("Parquet sink was already finished.".to_string())
+}
+
+impl<P: Serialize, W: Write + Send> ResultSink<P> for ParquetSink<W> {
+    fn write(&mut self, record: &TaskRecord<P>) -> Result<(), Error> {
+        if let State::Finished(_) | State::Closed = self.state {
+            return Err(finished());
+        }
+        if let Some(batch) = self
+            .builder
+            .push(record.to_row()?, record.error.is_none())?
+        {
+            self.write_batch(batch)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.finish_file()
+    }
+
+    fn dropped_columns(&self) -> &[String] {
+        self.builder.dropped_columns()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::TaskCommand;
+    use ::arrow::array::Array;
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReader;
+    use bytes::Bytes;
+    use std::time::Duration;
+
+    fn row(values: &[(&str, Value)]) -> IndexMap<String, Value> {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn values_not_fitting_the_schema_are_left_out() {
+        let mut builder = BatchBuilder::new(1);
+        let first = row(&[("metric.throughput", Value::from(1.5))]);
+        assert!(builder.push(first, true).unwrap().is_some());
+        let second = row(&[
+            ("metric.throughput", Value::from("n/a")),
+            ("metric.delay", Value::from(3.0)),
+        ]);
+        let batch = builder.push(second, true).unwrap().unwrap();
+        assert_eq!(batch.num_columns(), 1);
+        assert_eq!(batch.num_rows(), 1);
+        assert!(batch.column(0).is_null(0));
+        assert_eq!(
+            builder.dropped_columns(),
+            ["metric.throughput", "metric.delay"]
+        );
+    }
+
+    #[test]
+    fn first_batch_waits_for_a_succeeded_row() {
+        let mut builder = BatchBuilder::new(1);
+        let failed = row(&[("error", Value::from("timeout"))]);
+        assert!(builder.push(failed, false).unwrap().is_none());
+        let succeeded = row(&[("metric.throughput", Value::from(2))]);
+        let batch = builder.push(succeeded, true).unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let schema = builder.schema().unwrap();
+        assert_eq!(
+            schema
+                .field_with_name("metric.throughput")
+                .unwrap()
+                .data_type(),
+            &DataType::Float64
+        );
+    }
+
+    #[test]
+    fn metrics_are_floats_whatever_their_first_values() {
+        let mut builder = BatchBuilder::new(1);
+        let whole = row(&[
+            ("param.policy", Value::from(1)),
+            ("metric.throughput", Value::from(2)),
+        ]);
+        builder.push(whole, true).unwrap().unwrap();
+        let fractional = row(&[
+            ("param.policy", Value::from(2)),
+            ("metric.throughput", Value::from(2.5)),
+        ]);
+        let batch = builder.push(fractional, true).unwrap().unwrap();
+        let schema = batch.schema();
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        let throughput = batch.column(1).as_any().downcast_ref::<Float64Array>();
+        assert_eq!(throughput.unwrap().value(0), 2.5);
+    }
+
+    #[derive(Serialize)]
+    struct TestParam {
+        policy: u32,
+    }
+
+    fn write(sink: &mut ParquetSink<Vec<u8>>, index: usize, throughput: f64) -> Result<(), Error> {
+        let metrics = IndexMap::from([("throughput".to_string(), throughput)]);
+        sink.write(&TaskRecord {
+            experiment: "exp",
+            index,
+            param: &TestParam { policy: 1 },
+            command: &TaskCommand::run("/ns-3", "simple-ns3".to_string()),
+            cached: false,
+            attempts: 1,
+            elapsed: Duration::from_secs(1),
+            exit_code: Some(0),
+            metrics: &metrics,
+            stdout: "",
+            stderr: "",
+            error: None,
+        })
+    }
+
+    #[test]
+    fn parquet_files_read_back() {
+        let mut sink = ParquetSink::new(vec![]).batch_size(2);
+        for (index, throughput) in [1.0, 2.5, 4.0].into_iter().enumerate() {
+            write(&mut sink, index, throughput).unwrap();
+        }
+        ResultSink::<TestParam>::finish(&mut sink).unwrap();
+        assert!(write(&mut sink, 3, 5.0).is_err());
+
+        let file = Bytes::from(sink.into_inner().unwrap());
+        let reader = ParquetRecordBatchReader::try_new(file, 1024).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 3);
+        let index = batch.column_by_name("index").unwrap();
+        let index = index.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(index.values(), &[0, 1, 2]);
+        let throughput = batch.column_by_name("metric.throughput").unwrap();
+        let throughput = throughput.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(throughput.values(), &[1.0, 2.5, 4.0]);
+        let policy = batch.column_by_name("param.policy").unwrap();
+        assert_eq!(policy.data_type(), &DataType::Int64);
+    }
+}
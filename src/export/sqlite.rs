@@ -0,0 +1,618 @@
+//! SQLite results store
+//!
+//! `SqliteStore` keeps the records of every campaign run with it in one database, so results
+//! accumulated over many runs of the executor can be queried with SQL. It is both a
+//! `ResultSink` and a `ResultCache`, and a `RuntimeHistory` for `Executor::estimate_from`:
+//!
+//! ```no_run
+//! # use ns3_parallel::{BuildCmd, BuildParam, Executor, ExecutorBuilder};
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Default, Deserialize)]
+//! # struct MyConfig {}
+//! # #[derive(Serialize)]
+//! # struct MyParam {}
+//! # impl BuildParam<MyParam> for MyConfig {
+//! #     fn build_param(&self) -> Vec<MyParam> { vec![] }
+//! # }
+//! # impl BuildCmd for MyParam {
+//! #     fn build_cmd(&self) -> String { String::new() }
+//! # }
+//! # async fn run() -> Result<(), ns3_parallel::error::Error> {
+//! use ns3_parallel::export::sqlite::SqliteStore;
+//!
+//! let store = SqliteStore::open("results.db")?.label("baseline");
+//! let mut exe: Executor<MyConfig, MyParam> = ExecutorBuilder::new().build()?;
+//! exe.add_sink(store.clone());
+//! exe.resume_from(store.clone());
+//! exe.estimate_from(store);
+//! exe.execute().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! As a cache, the store only serves the output of a run made at the same `Revision`: the same
+//! ns-3 commit and config file. Nothing is served while the ns-3 tree has local modifications.
+//!
+//! ## Schema
+//!
+//! - `campaigns`: `id`, `label`, `started_at`, `finished_at`, `ns3_commit`, `ns3_dirty`,
+//!   `config_sha256`, one row per call to `Executor::execute`. Times are UNIX timestamps in
+//!   seconds.
+//! - `experiments`: `id`, `campaign_id`, `name`.
+//! - `params`: `id`, `json`, each distinct serialized param once, and `param_fields`:
+//!   `param_id`, `name`, `value`, its flattened fields.
+//! - `tasks`: `id`, `campaign_id`, `experiment_id`, `param_id`, `idx`, `command`, `status`,
+//!   `cached`, `attempts`, `elapsed`, `exit_code`, `error`, `stdout`, `stderr`, `finished_at`.
+//! - `metrics`: `task_id`, `name`, `value`.
+
+use indexmap::IndexMap;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::export::{
+    flatten, CachedTask, ResultCache, ResultSink, Revision, RuntimeHistory, TaskRecord,
+};
+use crate::manifest::{timestamp, Manifest};
+use crate::plan::TaskCommand;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS campaigns (
+    id INTEGER PRIMARY KEY,
+    label TEXT,
+    started_at REAL NOT NULL,
+    finished_at REAL,
+    ns3_commit TEXT,
+    ns3_dirty INTEGER,
+    config_sha256 TEXT
+);
+CREATE TABLE IF NOT EXISTS experiments (
+    id INTEGER PRIMARY KEY,
+    campaign_id INTEGER NOT NULL REFERENCES campaigns (id),
+    name TEXT NOT NULL,
+    UNIQUE (campaign_id, name)
+);
+CREATE TABLE IF NOT EXISTS params (
+    id INTEGER PRIMARY KEY,
+    json TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS param_fields (
+    param_id INTEGER NOT NULL REFERENCES params (id),
+    name TEXT NOT NULL,
+    value,
+    PRIMARY KEY (param_id, name)
+);
+CREATE TABLE IF NOT EXISTS tasks (
+    id INTEGER PRIMARY KEY,
+    campaign_id INTEGER NOT NULL REFERENCES campaigns (id),
+    experiment_id INTEGER NOT NULL REFERENCES experiments (id),
+    param_id INTEGER NOT NULL REFERENCES params (id),
+    idx INTEGER NOT NULL,
+    command TEXT NOT NULL,
+    status TEXT NOT NULL,
+    cached INTEGER NOT NULL,
+    attempts INTEGER NOT NULL,
+    elapsed REAL NOT NULL,
+    exit_code INTEGER,
+    error TEXT,
+    stdout BLOB,
+    stderr BLOB,
+    finished_at REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tasks_command ON tasks (command, status);
+CREATE TABLE IF NOT EXISTS metrics (
+    task_id INTEGER NOT NULL REFERENCES tasks (id),
+    name TEXT NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY (task_id, name)
+);
+";
+
+/// Columns added to `campaigns` after its first version, added to older databases when they
+/// are opened.
+const CAMPAIGN_COLUMNS: [(&str, &str); 3] = [
+    ("ns3_commit", "TEXT"),
+    ("ns3_dirty", "INTEGER"),
+    ("config_sha256", "TEXT"),
+];
+
+struct Store {
+    connection: Connection,
+    label: Option<String>,
+    keep_output: bool,
+    campaign: Option<i64>,
+    /// Revision of the campaign, set by `ResultSink::start`.
+    revision: Revision,
+    experiments: HashMap<String, i64>,
+}
+
+/// # SqliteStore
+///
+/// Handle to a results database, cheap to clone. A campaign is started by the first task
+/// written to it, or after `ResultSink::start`, and finished by `ResultSink::finish`.
+#[derive(Clone)]
+pub struct SqliteStore(Arc<Mutex<Store>>);
+
+impl fmt::Debug for SqliteStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SqliteStore")
+    }
+}
+
+impl SqliteStore {
+    /// Open the database at `path`, creating it and its tables when needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        Self::new(connection)
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(connection: Connection) -> Result<Self, Error> {
+        connection.execute_batch(SCHEMA)?;
+        let columns: Vec<String> = connection
+            .prepare("SELECT name FROM pragma_table_info('campaigns')")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for (name, kind) in CAMPAIGN_COLUMNS {
+            if !columns.iter().any(|c| c == name) {
+                connection.execute_batch(&format!(
+                    "ALTER TABLE campaigns ADD COLUMN {} {}",
+                    name, kind
+                ))?;
+            }
+        }
+        Ok(SqliteStore(Arc::new(Mutex::new(Store {
+            connection,
+            label: None,
+            keep_output: true,
+            campaign: None,
+            revision: Revision::default(),
+            experiments: HashMap::new(),
+        }))))
+    }
+
+    /// Label of the campaigns written from now on.
+    pub fn label(self, label: &str) -> Self {
+        self.0.lock().unwrap().label = Some(label.to_string());
+        self
+    }
+
+    /// Whether the stdout and stderr of tasks are stored. Default to `true`, which the store
+    /// needs to serve as a `ResultCache`.
+    pub fn keep_output(self, keep_output: bool) -> Self {
+        self.0.lock().unwrap().keep_output = keep_output;
+        self
+    }
+
+    /// Run `f` with the connection to the database, e.g. to query it.
+    pub fn with_connection<R>(&self, f: impl FnOnce(&Connection) -> R) -> R {
+        f(&self.0.lock().unwrap().connection)
+    }
+}
+
+impl Store {
+    fn campaign(&mut self) -> Result<i64, Error> {
+        if let Some(campaign) = self.campaign {
+            return Ok(campaign);
+        }
+        self.connection.execute(
+            "INSERT INTO campaigns (label, started_at, ns3_commit, ns3_dirty, config_sha256) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                self.label,
+                timestamp(),
+                self.revision.git_commit,
+                self.revision.git_dirty,
+                self.revision.config_sha256,
+            ],
+        )?;
+        let campaign = self.connection.last_insert_rowid();
+        self.campaign = Some(campaign);
+        self.experiments.clear();
+        Ok(campaign)
+    }
+
+    fn experiment(&mut self, campaign: i64, name: &str) -> Result<i64, Error> {
+        if let Some(id) = self.experiments.get(name) {
+            return Ok(*id);
+        }
+        self.connection.execute(
+            "INSERT INTO experiments (campaign_id, name) VALUES (?1, ?2)",
+            params![campaign, name],
+        )?;
+        let id = self.connection.last_insert_rowid();
+        self.experiments.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    fn param<P: Serialize>(&mut self, param: &P) -> Result<i64, Error> {
+        let json = serde_json::to_string(param)?;
+        let id = self
+            .connection
+            .query_row("SELECT id FROM params WHERE json = ?1", [&json], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        if let Some(id) = id {
+            return Ok(id);
+        }
+        self.connection
+            .execute("INSERT INTO params (json) VALUES (?1)", [&json])?;
+        let id = self.connection.last_insert_rowid();
+        let mut fields = IndexMap::new();
+        match serde_json::to_value(param)? {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    flatten(&key, value, &mut fields);
+                }
+            }
+            value => {
+                fields.insert("value".to_string(), value);
+            }
+        }
+        for (name, value) in fields {
+            self.connection.execute(
+                "INSERT INTO param_fields (param_id, name, value) VALUES (?1, ?2, ?3)",
+                params![id, name, sql_value(value)],
+            )?;
+        }
+        Ok(id)
+    }
+
+    fn write<P: Serialize>(&mut self, record: &TaskRecord<P>) -> Result<(), Error> {
+        let campaign = self.campaign()?;
+        self.connection.execute_batch("BEGIN")?;
+        let result = self.insert_task(campaign, record);
+        let end = match result {
+            Ok(_) => "COMMIT",
+            Err(_) => "ROLLBACK",
+        };
+        self.connection.execute_batch(end)?;
+        result
+    }
+
+    fn insert_task<P: Serialize>(
+        &mut self,
+        campaign: i64,
+        record: &TaskRecord<P>,
+    ) -> Result<(), Error> {
+        let experiment = self.experiment(campaign, record.experiment)?;
+        let param = self.param(record.param)?;
+        let status = match record.error {
+            None => "succeeded",
+            Some(_) => "failed",
+        };
+        let (stdout, stderr) = match self.keep_output {
+            true => (Some(record.stdout), Some(record.stderr)),
+            false => (None, None),
+        };
+        self.connection.execute(
+            "INSERT INTO tasks (campaign_id, experiment_id, param_id, idx, command, status, \
+             cached, attempts, elapsed, exit_code, error, stdout, stderr, finished_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                campaign,
+                experiment,
+                param,
+                record.index as i64,
+                record.command.to_string(),
+                status,
+                record.cached,
+                record.attempts,
+                record.elapsed.as_secs_f64(),
+                record.exit_code,
+                record.error.map(|e| format!("{:?}", e)),
+                stdout.map(str::as_bytes),
+                stderr.map(str::as_bytes),
+                timestamp(),
+            ],
+        )?;
+        let task = self.connection.last_insert_rowid();
+        for (name, value) in record.metrics {
+            self.connection.execute(
+                "INSERT INTO metrics (task_id, name, value) VALUES (?1, ?2, ?3)",
+                params![task, name, value],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: Serialize> ResultSink<P> for SqliteStore {
+    fn start(&mut self, manifest: &Manifest) -> Result<(), Error> {
+        let mut store = self.0.lock().unwrap();
+        store.revision = Revision::of(manifest);
+        store.campaign = None;
+        Ok(())
+    }
+
+    fn write(&mut self, record: &TaskRecord<P>) -> Result<(), Error> {
+        self.0.lock().unwrap().write(record)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        let mut store = self.0.lock().unwrap();
+        if let Some(campaign) = store.campaign.take() {
+            store.connection.execute(
+                "UPDATE campaigns SET finished_at = ?1 WHERE id = ?2",
+                params![timestamp(), campaign],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl ResultCache for SqliteStore {
+    /// The latest succeeded task with the same command, in any campaign at the same clean
+    /// revision, whose output was stored.
+    fn lookup(
+        &mut self,
+        command: &TaskCommand,
+        revision: &Revision,
+    ) -> Result<Option<CachedTask>, Error> {
+        if !revision.is_clean() {
+            return Ok(None);
+        }
+        let store = self.0.lock().unwrap();
+        let cached = store
+            .connection
+            .query_row(
+                "SELECT stdout, stderr, exit_code, attempts, elapsed FROM tasks \
+                 JOIN campaigns ON tasks.campaign_id = campaigns.id \
+                 WHERE command = ?1 AND status = 'succeeded' AND stdout IS NOT NULL \
+                 AND ns3_commit = ?2 AND ns3_dirty = 0 AND config_sha256 = ?3 \
+                 ORDER BY tasks.id DESC LIMIT 1",
+                params![
+                    command.to_string(),
+                    revision.git_commit,
+                    revision.config_sha256
+                ],
+                |row| {
+                    Ok(CachedTask {
+                        stdout: row.get(0)?,
+                        stderr: row.get::<_, Option<Vec<u8>>>(1)?.unwrap_or_default(),
+                        exit_code: row.get::<_, Option<i32>>(2)?.unwrap_or_default(),
+                        attempts: row.get(3)?,
+                        elapsed: Duration::from_secs_f64(row.get::<_, f64>(4)?.max(0.0)),
+                    })
+                },
+            )
+            .optional()?;
+        Ok(cached)
+    }
+}
+
+impl RuntimeHistory for SqliteStore {
+    /// The mean runtime of the succeeded runs of `command` that were not cached, in any
+    /// campaign, or else of the ones of the experiments named `experiment`.
+    fn runtime(
+        &mut self,
+        experiment: &str,
+        command: &TaskCommand,
+    ) -> Result<Option<Duration>, Error> {
+        let store = self.0.lock().unwrap();
+        let mut elapsed: Option<f64> = store.connection.query_row(
+            "SELECT AVG(elapsed) FROM tasks \
+             WHERE command = ?1 AND status = 'succeeded' AND cached = 0",
+            [command.to_string()],
+            |row| row.get(0),
+        )?;
+        if elapsed.is_none() {
+            elapsed = store.connection.query_row(
+                "SELECT AVG(tasks.elapsed) FROM tasks \
+                 JOIN experiments ON tasks.experiment_id = experiments.id \
+                 WHERE experiments.name = ?1 AND tasks.status = 'succeeded' AND tasks.cached = 0",
+                [experiment],
+                |row| row.get(0),
+            )?;
+        }
+        Ok(elapsed.map(|e| Duration::from_secs_f64(e.max(0.0))))
+    }
+}
+
+fn sql_value(value: Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqlValue::Text(s),
+        value => SqlValue::Text(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestParam {
+        policy: u32,
+        link: Link,
+    }
+
+    #[derive(Serialize)]
+    struct Link {
+        delay: f64,
+    }
+
+    fn revision(commit: &str) -> Revision {
+        Revision {
+            git_commit: Some(commit.to_string()),
+            git_dirty: Some(false),
+            config_sha256: "c0ffee".to_string(),
+        }
+    }
+
+    /// A store whose campaigns run at `revision`.
+    fn store(revision: Revision) -> SqliteStore {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store.0.lock().unwrap().revision = revision;
+        store
+    }
+
+    fn write(
+        store: &mut SqliteStore,
+        program: &str,
+        elapsed: u64,
+        cached: bool,
+        error: Option<&Error>,
+    ) {
+        let metrics = IndexMap::from([("throughput".to_string(), 12.5)]);
+        let record = TaskRecord {
+            experiment: "exp",
+            index: 0,
+            param: &TestParam {
+                policy: 1,
+                link: Link { delay: 0.5 },
+            },
+            command: &TaskCommand::run("/ns-3", program.to_string()),
+            cached,
+            attempts: 1,
+            elapsed: Duration::from_secs(elapsed),
+            exit_code: Some(0),
+            metrics: &metrics,
+            stdout: "throughput=12.5",
+            stderr: "",
+            error,
+        };
+        ResultSink::write(store, &record).unwrap();
+    }
+
+    fn lookup(store: &mut SqliteStore, program: &str, revision: &Revision) -> Option<CachedTask> {
+        let command = TaskCommand::run("/ns-3", program.to_string());
+        store.lookup(&command, revision).unwrap()
+    }
+
+    #[test]
+    fn records_are_stored() {
+        let mut store = store(revision("abc"));
+        write(&mut store, "simple-ns3", 1, false, None);
+        write(
+            &mut store,
+            "simple-ns3",
+            1,
+            false,
+            Some(&Error::RetryLimitExceed),
+        );
+        ResultSink::<TestParam>::finish(&mut store).unwrap();
+        store.with_connection(|c| {
+            let count = |sql: &str| c.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+            assert_eq!(
+                count("SELECT COUNT(*) FROM campaigns WHERE finished_at IS NOT NULL"),
+                1
+            );
+            assert_eq!(count("SELECT COUNT(*) FROM params"), 1);
+            assert_eq!(
+                count("SELECT COUNT(*) FROM tasks WHERE status = 'failed'"),
+                1
+            );
+            assert_eq!(count("SELECT COUNT(*) FROM metrics"), 2);
+            let delay: f64 = c
+                .query_row(
+                    "SELECT value FROM param_fields WHERE name = 'link.delay'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(delay, 0.5);
+            let commit: String = c
+                .query_row("SELECT ns3_commit FROM campaigns", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(commit, "abc");
+        });
+    }
+
+    #[test]
+    fn runtimes_are_read_back() {
+        let mut store = store(revision("abc"));
+        write(&mut store, "simple-ns3", 1, false, None);
+        write(&mut store, "simple-ns3", 3, false, None);
+        write(&mut store, "simple-ns3", 0, true, None);
+        write(
+            &mut store,
+            "other-ns3",
+            8,
+            false,
+            Some(&Error::RetryLimitExceed),
+        );
+        let command = |program: &str| TaskCommand::run("/ns-3", program.to_string());
+        let runtime = store.runtime("exp", &command("simple-ns3")).unwrap();
+        assert_eq!(runtime, Some(Duration::from_secs(2)));
+        // Unknown commands are estimated from their experiment, failed runs excluded.
+        let runtime = store.runtime("exp", &command("other-ns3")).unwrap();
+        assert_eq!(runtime, Some(Duration::from_secs(2)));
+        assert_eq!(store.runtime("new", &command("other-ns3")).unwrap(), None);
+    }
+
+    #[test]
+    fn cached_outputs_are_served_at_the_same_clean_revision() {
+        let mut store = store(revision("abc"));
+        write(&mut store, "simple-ns3", 1, false, None);
+        write(
+            &mut store,
+            "failed-ns3",
+            1,
+            false,
+            Some(&Error::RetryLimitExceed),
+        );
+
+        let hit = lookup(&mut store, "simple-ns3", &revision("abc")).unwrap();
+        assert_eq!(hit.stdout, b"throughput=12.5");
+        assert_eq!(hit.exit_code, 0);
+        assert_eq!(hit.elapsed, Duration::from_secs(1));
+
+        assert!(lookup(&mut store, "other-ns3", &revision("abc")).is_none());
+        assert!(lookup(&mut store, "failed-ns3", &revision("abc")).is_none());
+        assert!(lookup(&mut store, "simple-ns3", &revision("def")).is_none());
+        let config_changed = Revision {
+            config_sha256: "beef".to_string(),
+            ..revision("abc")
+        };
+        assert!(lookup(&mut store, "simple-ns3", &config_changed).is_none());
+        let dirty = Revision {
+            git_dirty: Some(true),
+            ..revision("abc")
+        };
+        assert!(lookup(&mut store, "simple-ns3", &dirty).is_none());
+    }
+
+    #[test]
+    fn runs_of_a_dirty_tree_are_never_served() {
+        let dirty = Revision {
+            git_dirty: Some(true),
+            ..revision("abc")
+        };
+        let mut store = store(dirty);
+        write(&mut store, "simple-ns3", 1, false, None);
+        assert!(lookup(&mut store, "simple-ns3", &revision("abc")).is_none());
+    }
+
+    #[test]
+    fn older_databases_get_the_revision_columns() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE campaigns (id INTEGER PRIMARY KEY, label TEXT, \
+                 started_at REAL NOT NULL, finished_at REAL)",
+            )
+            .unwrap();
+        let mut store = SqliteStore::new(connection).unwrap();
+        store.0.lock().unwrap().revision = revision("abc");
+        write(&mut store, "simple-ns3", 1, false, None);
+        assert!(lookup(&mut store, "simple-ns3", &revision("abc")).is_some());
+    }
+}
@@ -0,0 +1,142 @@
+//! Hooks run by the executor on each task
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::core::BuildCmd;
+use crate::error::Error;
+use crate::executor::Task;
+
+/// # PostProcess
+///
+/// This trait is used to turn the output of each finished task into a typed result, inside the
+/// executor and while the other tasks keep running.
+///
+/// Pass an implementation to `ExecutorBuilder::post_process`. The result is stored in
+/// `Task::processed`, and an `Err` makes the task fail. It is implemented for closures taking a
+/// `&Task<P>`, so most of the time there is no need to implement it by hand.
+///
+/// `process` is called on the blocking thread pool of tokio, so the executor keeps driving the
+/// other tasks meanwhile. It holds one of the `task_concurrent` slots while it runs, so heavy
+/// processing slows down the launch of the next tasks instead of piling up.
+///
+/// ## Example
+///
+/// ```no_run
+/// # use ns3_parallel::{BuildCmd, BuildParam, Executor, ExecutorBuilder};
+/// # #[derive(Default, serde::Deserialize)]
+/// # struct MyConfig {}
+/// # struct MyParam {}
+/// # impl BuildParam<MyParam> for MyConfig {
+/// #     fn build_param(&self) -> Vec<MyParam> { vec![] }
+/// # }
+/// # impl BuildCmd for MyParam {
+/// #     fn build_cmd(&self) -> String { String::new() }
+/// # }
+/// let exe: Executor<MyConfig, MyParam, usize> = ExecutorBuilder::new()
+///     .post_process(|task: &ns3_parallel::executor::Task<MyParam>| {
+///         Ok(task.read_stdout().lines().count())
+///     })
+///     .keep_raw_output(false)
+///     .build()
+///     .unwrap();
+/// ```
+pub trait PostProcess<P: BuildCmd>: Send + Sync {
+    type Output;
+
+    fn process(&self, task: &Task<P>) -> Result<Self::Output, Error>;
+}
+
+impl<P, O, F> PostProcess<P> for F
+where
+    P: BuildCmd,
+    F: Fn(&Task<P>) -> Result<O, Error> + Send + Sync,
+{
+    type Output = O;
+
+    fn process(&self, task: &Task<P>) -> Result<O, Error> {
+        self(task)
+    }
+}
+
+/// Default of `ExecutorBuilder::post_process`, which leaves `Task::processed` as `()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoPostProcess;
+
+impl<P: BuildCmd> PostProcess<P> for NoPostProcess {
+    type Output = ();
+
+    fn process(&self, _task: &Task<P>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The post-processing hook held by an executor.
+pub(crate) struct PostProcessor<P, O>(pub Arc<dyn PostProcess<P, Output = O>>);
+
+impl<P, O> Clone for PostProcessor<P, O> {
+    fn clone(&self) -> Self {
+        PostProcessor(self.0.clone())
+    }
+}
+
+impl<P: BuildCmd + Send + 'static, O: Send + 'static> PostProcessor<P, O> {
+    /// Process `task` on the blocking thread pool, and give it back along with the result.
+    pub async fn process(&self, task: Task<P>) -> (Task<P>, Result<O, Error>) {
+        let hook = self.0.clone();
+        let processed = tokio::task::spawn_blocking(move || {
+            let processed = hook.process(&task);
+            (task, processed)
+        })
+        .await;
+        match processed {
+            Ok(processed) => processed,
+            Err(error) => std::panic::resume_unwind(error.into_panic()),
+        }
+    }
+}
+
+impl<P, O> fmt::Debug for PostProcessor<P, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PostProcessor")
+    }
+}
+
+/// # PreRun
+///
+/// A task about to be launched, as seen by the hook set with `Executor::pre_run`.
+///
+/// The hook may write input files to `work_dir`, and change `argument` and `env` to adjust the
+/// command of the task.
+#[derive(Debug)]
+pub struct PreRun<'a, P> {
+    pub experiment: &'a str,
+    /// Position of `param` in the params returned by `BuildParam::build_param`.
+    pub index: usize,
+    pub param: &'a P,
+    /// Directory the ns-3 program runs in, created beforehand when `ExecutorBuilder::output_dir`
+    /// is set.
+    pub work_dir: &'a Path,
+    /// Program and arguments passed to `waf --run-no-build`, from `BuildCmd::build_cmd`.
+    pub argument: String,
+    /// Environment variables set for the task.
+    pub env: Vec<(String, String)>,
+}
+
+pub(crate) type PreRunFn<P> = dyn Fn(&mut PreRun<'_, P>) -> Result<(), Error> + Send + Sync;
+
+/// The pre-run hook held by an executor.
+pub(crate) struct PreRunHook<P>(pub Arc<PreRunFn<P>>);
+
+impl<P> Clone for PreRunHook<P> {
+    fn clone(&self) -> Self {
+        PreRunHook(self.0.clone())
+    }
+}
+
+impl<P> fmt::Debug for PreRunHook<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PreRunHook")
+    }
+}
@@ -1,6 +1,22 @@
+pub mod attribute;
+pub mod config;
 pub mod core;
+pub mod crash;
 pub mod error;
+pub mod event;
 pub mod executor;
+pub mod export;
+pub mod hook;
+pub mod limits;
+pub mod manifest;
+pub mod metrics;
+pub mod plan;
+mod process;
+pub mod progress;
+pub mod rerun;
+pub mod results;
+pub mod schedule;
+pub mod wrapper;
 
 pub use crate::core::{BuildCmd, BuildParam};
 pub use crate::executor::{Executor, ExecutorBuilder};
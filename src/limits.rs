@@ -0,0 +1,222 @@
+//! Resource limits of tasks
+//!
+//! `ResourceLimits` are applied with `setrlimit` to waf and, through it, to the ns-3 program of
+//! each task, so a misconfigured simulation fails on its own instead of exhausting the host.
+//! Limits are set for every task with `ExecutorBuilder::limits`, and for the task of one param
+//! with `BuildCmd::build_limits`, whose limits win over the ones of the executor.
+//!
+//! A task exceeding a limit fails with `Error::LimitExceeded` without being retried, since it
+//! would exceed it again:
+//!
+//! - `address_space`: allocations fail, which ns-3 programs report as an uncaught
+//!   `std::bad_alloc`. Linux does not enforce a limit on the resident set size, so the virtual
+//!   address space is limited instead, which is larger than the memory actually used.
+//! - `cpu_time`: the program receives `SIGXCPU`.
+//! - `file_size`: writing past the limit raises `SIGXFSZ`.
+//! - `open_files`: opening a file fails with `Too many open files`.
+//!
+//! ## Example
+//!
+//! ```
+//! use ns3_parallel::limits::ResourceLimits;
+//! use std::time::Duration;
+//!
+//! let limits = ResourceLimits::new()
+//!     .address_space(8 << 30)
+//!     .cpu_time(Duration::from_secs(3600));
+//! let param_limits = ResourceLimits::new().address_space(16 << 30);
+//! let merged = limits.with(&param_limits);
+//! assert_eq!(merged.address_space, Some(16 << 30));
+//! assert_eq!(merged.cpu_time, Some(Duration::from_secs(3600)));
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::process::Output;
+use std::time::Duration;
+
+use crate::crash::Crash;
+
+/// # ResourceLimits
+///
+/// Limits applied to the processes of a task, see the module documentation. Unset limits are
+/// inherited from the executor.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceLimits {
+    /// Virtual address space, in bytes, `RLIMIT_AS`.
+    pub address_space: Option<u64>,
+    /// CPU time of each process, `RLIMIT_CPU`, rounded up to the second.
+    pub cpu_time: Option<Duration>,
+    /// Size of the files written, in bytes, `RLIMIT_FSIZE`.
+    pub file_size: Option<u64>,
+    /// Number of open file descriptors, `RLIMIT_NOFILE`.
+    pub open_files: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn address_space(mut self, bytes: u64) -> Self {
+        self.address_space = Some(bytes);
+        self
+    }
+
+    pub fn cpu_time(mut self, cpu_time: Duration) -> Self {
+        self.cpu_time = Some(cpu_time);
+        self
+    }
+
+    pub fn file_size(mut self, bytes: u64) -> Self {
+        self.file_size = Some(bytes);
+        self
+    }
+
+    pub fn open_files(mut self, open_files: u64) -> Self {
+        self.open_files = Some(open_files);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// These limits, overridden by the ones set in `other`.
+    pub fn with(&self, other: &ResourceLimits) -> Self {
+        ResourceLimits {
+            address_space: other.address_space.or(self.address_space),
+            cpu_time: other.cpu_time.or(self.cpu_time),
+            file_size: other.file_size.or(self.file_size),
+            open_files: other.open_files.or(self.open_files),
+        }
+    }
+
+    /// Lower the soft limits of the calling process, see `set_soft_limit`. Hard limits are left
+    /// as they are.
+    pub(crate) fn apply(&self) -> std::io::Result<()> {
+        let cpu_time = self
+            .cpu_time
+            .map(|t| t.as_secs() + u64::from(t.subsec_nanos() > 0));
+        let limits = [
+            (libc::RLIMIT_AS, self.address_space),
+            (libc::RLIMIT_CPU, cpu_time),
+            (libc::RLIMIT_FSIZE, self.file_size),
+            (libc::RLIMIT_NOFILE, self.open_files),
+        ];
+        for (resource, value) in limits {
+            if let Some(value) = value {
+                set_soft_limit(resource, value as libc::rlim_t)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The limit a failed attempt exceeded, described for `Error::LimitExceeded`, if it was
+    /// stopped by one.
+    ///
+    /// The CPU time and file size limits are told by the signal of `crash`, taken from the exit
+    /// status unless waf hid it. The address space and open files limits make a call fail
+    /// instead of sending a signal, so the messages of the C++ runtime and of `strerror` are
+    /// looked for on stderr, unless the attempt was killed by another signal than the `SIGABRT`
+    /// of an uncaught exception.
+    pub(crate) fn exceeded(&self, output: &Output, crash: Option<&Crash>) -> Option<String> {
+        let signal = crash.map(|c| c.signal.as_str());
+        match (signal, self.cpu_time, self.file_size) {
+            (Some("SIGXCPU"), Some(cpu_time), _) => {
+                return Some(format!("CPU time limit of {:?} exceeded.", cpu_time));
+            }
+            (Some("SIGXFSZ"), _, Some(file_size)) => {
+                return Some(format!("File size limit of {} bytes exceeded.", file_size));
+            }
+            (None | Some("SIGABRT"), _, _) => {}
+            _ => return None,
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Some(address_space) = self.address_space {
+            if stderr.contains("std::bad_alloc") || stderr.contains("Cannot allocate memory") {
+                return Some(format!(
+                    "Address space limit of {} bytes exceeded.",
+                    address_space
+                ));
+            }
+        }
+        if let Some(open_files) = self.open_files {
+            if stderr.contains("Too many open files") {
+                return Some(format!("Open files limit of {} exceeded.", open_files));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = libc::c_int;
+
+/// Set the soft limit of `resource` to `value`, lowered to the hard limit. Called in the child
+/// between fork and exec, so it only uses async-signal-safe functions.
+pub(crate) fn set_soft_limit(resource: Resource, value: libc::rlim_t) -> std::io::Result<()> {
+    // SAFETY: getrlimit and setrlimit only access the struct they are given.
+    unsafe {
+        let mut limit: libc::rlimit = std::mem::zeroed();
+        if libc::getrlimit(resource, &mut limit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        limit.rlim_cur = value.min(limit.rlim_max);
+        if libc::setrlimit(resource, &limit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn exceeded(limits: &ResourceLimits, status: i32, stderr: &str) -> Option<String> {
+        let output = Output {
+            status: ExitStatus::from_raw(status),
+            stdout: vec![],
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        limits.exceeded(&output, Crash::detect(&output).as_ref())
+    }
+
+    #[test]
+    fn signals_tell_cpu_time_and_file_size() {
+        let limits = ResourceLimits::new()
+            .cpu_time(Duration::from_secs(60))
+            .file_size(1 << 20);
+        assert!(exceeded(&limits, libc::SIGXCPU, "")
+            .unwrap()
+            .contains("CPU time"));
+        assert!(exceeded(&limits, libc::SIGXFSZ, "")
+            .unwrap()
+            .contains("File size"));
+        assert_eq!(exceeded(&limits, libc::SIGSEGV, ""), None);
+        assert_eq!(exceeded(&ResourceLimits::new(), libc::SIGXCPU, ""), None);
+    }
+
+    #[test]
+    fn failed_calls_are_read_from_stderr() {
+        let limits = ResourceLimits::new().address_space(1 << 30).open_files(64);
+        let bad_alloc = "terminate called after throwing an instance of 'std::bad_alloc'\n";
+        assert!(exceeded(&limits, libc::SIGABRT, bad_alloc)
+            .unwrap()
+            .contains("Address space"));
+        let open_files = "open: Too many open files\n";
+        assert!(exceeded(&limits, 1 << 8, open_files)
+            .unwrap()
+            .contains("Open files"));
+        // Killed by an unrelated signal after the message was printed.
+        assert_eq!(exceeded(&limits, libc::SIGSEGV, open_files), None);
+        assert_eq!(
+            exceeded(&ResourceLimits::new(), libc::SIGABRT, bad_alloc),
+            None
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Reproducibility manifest
+//!
+//! A `Manifest` records what produced the results of a campaign: the crate version, the config
+//! file and its hash, the configs as written in the file, the ns-3 revision and build profile,
+//! the host, the environment overrides, and the command line and seed of every task.
+//!
+//! `Executor::execute` writes it as JSON to `ExecutorBuilder::manifest_path`, by default
+//! `manifest.json` in the output directory. Without one, it goes to the ns-3 directory under a
+//! name carrying the start time of the campaign, `manifest-<unix millis>.json`, so that
+//! campaigns sharing the ns-3 tree do not overwrite each other's manifest. It is written when
+//! the campaign starts, and again when it ends with the commands as changed by the pre-run hook.
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ffi::CStr;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::attribute::AttributeOverride;
+use crate::error::Error;
+use crate::executor::ConfigFormat;
+use crate::plan::TaskCommand;
+
+/// Name of the manifest written in the output directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Name of the manifest written in the ns-3 directory by a campaign without output directory.
+pub(crate) fn timestamped_manifest_file() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("manifest-{}.json", millis)
+}
+
+/// # Manifest
+///
+/// See the module documentation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub crate_version: String,
+    /// UNIX timestamp, in seconds, of the start of the campaign.
+    pub created_at: f64,
+    pub config_path: String,
+    pub config_format: ConfigFormat,
+    /// SHA-256 of the config file, in hex.
+    pub config_sha256: String,
+    /// Every experiment of the config file, as written in it.
+    pub configs: IndexMap<String, serde_json::Value>,
+    pub ns3: Ns3Info,
+    pub host: HostInfo,
+    /// Environment variables of each experiment, from its `env` block.
+    pub env: IndexMap<String, Vec<(String, String)>>,
+    /// Tasks in launch order.
+    pub tasks: Vec<ManifestTask>,
+}
+
+/// The ns-3 tree the tasks ran in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ns3Info {
+    pub path: String,
+    /// `None` when the tree is not a git repository.
+    pub git_commit: Option<String>,
+    /// Whether tracked files were modified since `git_commit`.
+    pub git_dirty: Option<bool>,
+    /// Profile waf was configured with, e.g. `optimized`.
+    pub build_profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub hostname: String,
+    pub os: String,
+    /// Kernel release, e.g. `6.1.0-13-amd64`.
+    pub kernel: Option<String>,
+    pub arch: String,
+    pub cpus: usize,
+}
+
+/// # ManifestTask
+///
+/// A task of the campaign and the exact command it ran, environment variables and working
+/// directory included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestTask {
+    pub experiment: String,
+    pub index: usize,
+    pub command: TaskCommand,
+    /// The `RngSeed` global value of the task, when it sets one.
+    pub rng_seed: Option<u64>,
+    /// The `RngRun` global value of the task, when it sets one.
+    pub rng_run: Option<u64>,
+}
+
+impl Manifest {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn task(&self, experiment: &str, index: usize) -> Option<&ManifestTask> {
+        self.tasks
+            .iter()
+            .find(|t| t.experiment == experiment && t.index == index)
+    }
+}
+
+impl ManifestTask {
+    pub(crate) fn new(
+        experiment: &str,
+        index: usize,
+        command: TaskCommand,
+        attributes: &[AttributeOverride],
+    ) -> Self {
+        ManifestTask {
+            experiment: experiment.to_string(),
+            index,
+            rng_seed: global_value(attributes, &command, "RngSeed"),
+            rng_run: global_value(attributes, &command, "RngRun"),
+            command,
+        }
+    }
+}
+
+/// The global value `name` set by a task: by its attribute overrides, else on its command
+/// line, else through `NS_GLOBAL_VALUE`.
+fn global_value(
+    attributes: &[AttributeOverride],
+    command: &TaskCommand,
+    name: &str,
+) -> Option<u64> {
+    if let Some(value) = attributes.iter().find(|a| a.name() == name) {
+        return value.value().to_string().parse().ok();
+    }
+    let flag = format!("--{}=", name);
+    let from_args = command.args.iter().find_map(|arg| {
+        let start = arg.rfind(&flag)? + flag.len();
+        let digits: String = arg[start..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        digits.parse().ok()
+    });
+    if from_args.is_some() {
+        return from_args;
+    }
+    let (_, values) = command.env.iter().find(|(k, _)| k == "NS_GLOBAL_VALUE")?;
+    values
+        .split(';')
+        .filter_map(|v| v.split_once('='))
+        .find(|(k, _)| *k == name)
+        .and_then(|(_, v)| v.trim().parse().ok())
+}
+
+impl Ns3Info {
+    pub(crate) fn probe(ns3_path: &Path) -> Self {
+        let git = |args: &[&str]| -> Option<String> {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(ns3_path)
+                .args(args)
+                .output()
+                .ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        let git_commit = git(&["rev-parse", "HEAD"]);
+        let git_dirty = git_commit
+            .as_ref()
+            .and_then(|_| git(&["status", "--porcelain", "--untracked-files=no"]))
+            .map(|status| !status.is_empty());
+        Ns3Info {
+            path: ns3_path.display().to_string(),
+            git_commit,
+            git_dirty,
+            build_profile: build_profile(ns3_path),
+        }
+    }
+}
+
+/// Build profile from the waf cache, or from the CMake cache of newer ns-3 trees.
+fn build_profile(ns3_path: &Path) -> Option<String> {
+    if let Ok(cache) = std::fs::read_to_string(ns3_path.join("build/c4che/_cache.py")) {
+        let line = cache.lines().find(|l| l.starts_with("BUILD_PROFILE"))?;
+        let value = line.split_once('=')?.1.trim();
+        return Some(value.trim_matches(|c| c == '\'' || c == '"').to_string());
+    }
+    let cache = std::fs::read_to_string(ns3_path.join("cmake-cache/CMakeCache.txt")).ok()?;
+    let line = cache.lines().find(|l| l.starts_with("CMAKE_BUILD_TYPE:"))?;
+    Some(line.split_once('=')?.1.trim().to_string())
+}
+
+impl HostInfo {
+    pub(crate) fn probe() -> Self {
+        // SAFETY: uname only writes to the struct it is given, which outlives the call.
+        let uts = unsafe {
+            let mut uts: libc::utsname = std::mem::zeroed();
+            (libc::uname(&mut uts) == 0).then_some(uts)
+        };
+        let field = |f: &[libc::c_char]| {
+            // SAFETY: uname fills its fields with NUL-terminated strings.
+            unsafe { CStr::from_ptr(f.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        HostInfo {
+            hostname: uts.as_ref().map(|u| field(&u.nodename)).unwrap_or_default(),
+            os: std::env::consts::OS.to_string(),
+            kernel: uts.as_ref().map(|u| field(&u.release)),
+            arch: std::env::consts::ARCH.to_string(),
+            cpus: num_cpus::get(),
+        }
+    }
+}
+
+pub(crate) fn sha256_file(path: impl AsRef<Path>) -> Result<String, Error> {
+    let digest = Sha256::digest(std::fs::read(path)?);
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Seconds since the Unix epoch.
+pub(crate) fn timestamp() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str], env: &[(&str, &str)]) -> TaskCommand {
+        TaskCommand {
+            program: "waf".into(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: env
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            cwd: ".".into(),
+        }
+    }
+
+    #[test]
+    fn global_values_are_read_from_the_args() {
+        let cmd = command(&["--run", "exp --RngRun=3 --other=1"], &[]);
+        assert_eq!(global_value(&[], &cmd, "RngRun"), Some(3));
+        assert_eq!(global_value(&[], &cmd, "RngSeed"), None);
+
+        let cmd = command(&["--run", "exp --RngRun=3 --RngRun=12ms"], &[]);
+        assert_eq!(global_value(&[], &cmd, "RngRun"), Some(12));
+        let cmd = command(&["--RngRun=abc"], &[]);
+        assert_eq!(global_value(&[], &cmd, "RngRun"), None);
+    }
+
+    #[test]
+    fn global_values_are_read_from_the_environment() {
+        let cmd = command(&[], &[("NS_GLOBAL_VALUE", "RngSeed=5;RngRun= 7 ")]);
+        assert_eq!(global_value(&[], &cmd, "RngSeed"), Some(5));
+        assert_eq!(global_value(&[], &cmd, "RngRun"), Some(7));
+        assert_eq!(global_value(&[], &cmd, "SchedulerType"), None);
+
+        let cmd = command(&[], &[("NS_LOG", "RngRun=7")]);
+        assert_eq!(global_value(&[], &cmd, "RngRun"), None);
+    }
+
+    #[test]
+    fn global_values_follow_attributes_then_args_then_environment() {
+        let cmd = command(
+            &["--RngRun=3"],
+            &[("NS_GLOBAL_VALUE", "RngRun=7;RngSeed=5")],
+        );
+        let attributes = [AttributeOverride::global("RngRun", 9u64).unwrap()];
+        assert_eq!(global_value(&attributes, &cmd, "RngRun"), Some(9));
+        assert_eq!(global_value(&[], &cmd, "RngRun"), Some(3));
+        assert_eq!(global_value(&attributes, &cmd, "RngSeed"), Some(5));
+    }
+
+    #[test]
+    fn timestamped_manifest_names() {
+        let name = timestamped_manifest_file();
+        let millis = name
+            .strip_prefix("manifest-")
+            .and_then(|n| n.strip_suffix(".json"))
+            .unwrap();
+        assert!(millis.parse::<u128>().is_ok());
+    }
+}
@@ -0,0 +1,402 @@
+//! Metric extraction from the output of tasks
+//!
+//! A `MetricRule` describes how to find named numeric metrics in the stdout or stderr of a
+//! task. Rules are set for every experiment with `ExecutorBuilder::metrics`, or for one
+//! experiment with its reserved `metrics` key in the config file:
+//!
+//! ```toml
+//! [exp-1]
+//! policy = [1, 2, 3]
+//! metrics = [
+//!     { mode = "regex", regex = 'throughput=(?P<throughput>[0-9.]+) Mbps', required = ["throughput"] },
+//!     { mode = "key_value", separator = ":", keys = ["rtt"] },
+//! ]
+//! ```
+//!
+//! ## Example
+//!
+//! ```
+//! use ns3_parallel::metrics::{MetricExtractor, MetricMode, MetricRule};
+//!
+//! let extractor = MetricExtractor::new(&[
+//!     MetricRule::regex(r"throughput=(?P<throughput>[0-9.]+)").required(&["throughput"]),
+//!     MetricRule::new(MetricMode::JsonLines),
+//! ])
+//! .unwrap();
+//! let stdout = "throughput=12.5 Mbps\n{\"loss\": 0.01, \"delay\": {\"mean\": 20}}\n";
+//! let metrics = extractor.extract(stdout, "").unwrap();
+//! assert_eq!(metrics["throughput"], 12.5);
+//! assert_eq!(metrics["delay.mean"], 20.0);
+//! assert!(extractor.extract("", "").is_err());
+//! ```
+
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Output of a task a `MetricRule` reads. Default to `MetricSource::Stdout`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricSource {
+    #[default]
+    Stdout,
+    Stderr,
+}
+
+/// How a `MetricRule` finds metrics.
+///
+/// - `Regex`: every named capture group of `regex` is a metric.
+/// - `KeyValue`: pairs such as `throughput=12.5`, `separator` being `=` by default.
+/// - `Csv`: the first line holding `delimiter`, `,` by default, is the header, and the numeric
+///   fields of the last row with as many fields are metrics named after their column.
+/// - `JsonLines`: numeric fields of the lines holding a JSON object, nested fields being named
+///   with their path joined by `.`.
+///
+/// When a metric is found several times, the last value is kept.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricMode {
+    Regex,
+    KeyValue,
+    Csv,
+    JsonLines,
+}
+
+/// # MetricRule
+///
+/// A declarative rule extracting metrics from the output of a task.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MetricRule {
+    pub mode: MetricMode,
+    #[serde(default)]
+    pub source: MetricSource,
+    /// Pattern of `MetricMode::Regex`.
+    #[serde(default)]
+    pub regex: Option<String>,
+    /// Separator of `MetricMode::KeyValue`.
+    #[serde(default)]
+    pub separator: Option<String>,
+    /// Delimiter of `MetricMode::Csv`.
+    #[serde(default)]
+    pub delimiter: Option<char>,
+    /// Only keep these metrics. Default to all the metrics found.
+    #[serde(default)]
+    pub keys: Option<Vec<String>>,
+    /// Metrics that must be found, the task fails otherwise.
+    #[serde(default)]
+    pub required: Vec<String>,
+}
+
+impl MetricRule {
+    pub fn new(mode: MetricMode) -> Self {
+        MetricRule {
+            mode,
+            source: MetricSource::Stdout,
+            regex: None,
+            separator: None,
+            delimiter: None,
+            keys: None,
+            required: vec![],
+        }
+    }
+
+    pub fn regex(regex: &str) -> Self {
+        MetricRule {
+            regex: Some(regex.to_string()),
+            ..Self::new(MetricMode::Regex)
+        }
+    }
+
+    pub fn source(mut self, source: MetricSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn separator(mut self, separator: &str) -> Self {
+        self.separator = Some(separator.to_string());
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    pub fn keys(mut self, keys: &[&str]) -> Self {
+        self.keys = Some(keys.iter().map(|k| k.to_string()).collect());
+        self
+    }
+
+    pub fn required(mut self, required: &[&str]) -> Self {
+        self.required = required.iter().map(|k| k.to_string()).collect();
+        self
+    }
+}
+
+const NUMBER: &str = r"[-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?";
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Regex(Regex),
+    /// Pairs matched by the `key` and `value` groups.
+    KeyValue(Regex),
+    Csv(char),
+    JsonLines,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    matcher: Matcher,
+    source: MetricSource,
+    keys: Option<Vec<String>>,
+}
+
+/// # MetricExtractor
+///
+/// A set of `MetricRule`s, checked and compiled.
+#[derive(Debug, Clone, Default)]
+pub struct MetricExtractor {
+    rules: Vec<CompiledRule>,
+    required: Vec<String>,
+}
+
+impl MetricExtractor {
+    pub fn new(rules: &[MetricRule]) -> Result<Self, Error> {
+        let mut extractor = MetricExtractor::default();
+        for rule in rules {
+            let matcher = match rule.mode {
+                MetricMode::Regex => {
+                    let regex = rule.regex.as_deref().ok_or_else(|| {
+                        Error::InvalidConfig("Metric rule in regex mode without regex.".to_string())
+                    })?;
+                    Matcher::Regex(compile(regex)?)
+                }
+                MetricMode::KeyValue => {
+                    let separator = regex::escape(rule.separator.as_deref().unwrap_or("="));
+                    Matcher::KeyValue(compile(&format!(
+                        r"(?P<key>[A-Za-z_][\w.\-/]*)\s*{}\s*(?P<value>{})",
+                        separator, NUMBER
+                    ))?)
+                }
+                MetricMode::Csv => Matcher::Csv(rule.delimiter.unwrap_or(',')),
+                MetricMode::JsonLines => Matcher::JsonLines,
+            };
+            extractor.rules.push(CompiledRule {
+                matcher,
+                source: rule.source,
+                keys: rule.keys.clone(),
+            });
+            extractor.required.extend(rule.required.iter().cloned());
+        }
+        Ok(extractor)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Extract the metrics of a task from its stdout and stderr, in the order they were found.
+    pub fn extract(&self, stdout: &str, stderr: &str) -> Result<IndexMap<String, f64>, Error> {
+        let mut metrics = IndexMap::new();
+        for rule in &self.rules {
+            let text = match rule.source {
+                MetricSource::Stdout => stdout,
+                MetricSource::Stderr => stderr,
+            };
+            let mut found = IndexMap::new();
+            match &rule.matcher {
+                Matcher::Regex(regex) => extract_regex(regex, text, &mut found),
+                Matcher::KeyValue(regex) => extract_key_value(regex, text, &mut found),
+                Matcher::Csv(delimiter) => extract_csv(*delimiter, text, &mut found),
+                Matcher::JsonLines => extract_json_lines(text, &mut found),
+            }
+            for (key, value) in found {
+                if rule.keys.as_ref().is_none_or(|keys| keys.contains(&key)) {
+                    metrics.insert(key, value);
+                }
+            }
+        }
+        let missing: Vec<&str> = self
+            .required
+            .iter()
+            .filter(|k| !metrics.contains_key(*k))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::MissingMetric(missing.join(", ")));
+        }
+        Ok(metrics)
+    }
+}
+
+fn compile(regex: &str) -> Result<Regex, Error> {
+    Regex::new(regex).map_err(|e| Error::InvalidConfig(format!("{:?}", e)))
+}
+
+/// Named groups of `regex` as metrics.
+fn extract_regex(regex: &Regex, text: &str, found: &mut IndexMap<String, f64>) {
+    let names: Vec<&str> = regex.capture_names().flatten().collect();
+    for captures in regex.captures_iter(text) {
+        for name in &names {
+            if let Some(value) = captures.name(name).and_then(|m| m.as_str().parse().ok()) {
+                found.insert(name.to_string(), value);
+            }
+        }
+    }
+}
+
+/// Pairs of the `key` and `value` groups of `regex` as metrics.
+fn extract_key_value(regex: &Regex, text: &str, found: &mut IndexMap<String, f64>) {
+    for captures in regex.captures_iter(text) {
+        if let Ok(value) = captures["value"].parse() {
+            found.insert(captures["key"].to_string(), value);
+        }
+    }
+}
+
+fn extract_csv(delimiter: char, text: &str, found: &mut IndexMap<String, f64>) {
+    let mut lines = text.lines().skip_while(|l| !l.contains(delimiter));
+    let Some(header) = lines.next() else {
+        return;
+    };
+    let columns: Vec<&str> = header.split(delimiter).map(str::trim).collect();
+    let last = lines
+        .filter(|l| l.split(delimiter).count() == columns.len())
+        .last();
+    if let Some(row) = last {
+        for (column, field) in columns.iter().zip(row.split(delimiter)) {
+            if let Ok(value) = field.trim().parse() {
+                found.insert(column.to_string(), value);
+            }
+        }
+    }
+}
+
+fn extract_json_lines(text: &str, found: &mut IndexMap<String, f64>) {
+    for line in text.lines().map(str::trim).filter(|l| l.starts_with('{')) {
+        if let Ok(serde_json::Value::Object(object)) = serde_json::from_str(line) {
+            flatten_json("", &object, found);
+        }
+    }
+}
+
+fn flatten_json(
+    prefix: &str,
+    object: &serde_json::Map<String, serde_json::Value>,
+    found: &mut IndexMap<String, f64>,
+) {
+    for (key, value) in object {
+        let key = match prefix {
+            "" => key.to_owned(),
+            _ => format!("{}.{}", prefix, key),
+        };
+        match value {
+            serde_json::Value::Number(n) => {
+                if let Some(n) = n.as_f64() {
+                    found.insert(key, n);
+                }
+            }
+            serde_json::Value::Object(object) => flatten_json(&key, object, found),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(rules: &[MetricRule], stdout: &str) -> IndexMap<String, f64> {
+        MetricExtractor::new(rules)
+            .unwrap()
+            .extract(stdout, "")
+            .unwrap()
+    }
+
+    #[test]
+    fn regex_keeps_the_last_match_of_every_group() {
+        let rule = MetricRule::regex(r"rx=(?P<rx>\d+) tx=(?P<tx>\d+)");
+        let metrics = extract(&[rule], "rx=1 tx=2\nrx=3 tx=4\nrx=? tx=5\n");
+        assert_eq!(
+            metrics,
+            IndexMap::from([("rx".into(), 3.0), ("tx".into(), 4.0)])
+        );
+    }
+
+    #[test]
+    fn regex_groups_named_key_and_value_are_metrics() {
+        let rule = MetricRule::regex(r"(?P<key>\d+) -> (?P<value>\d+)");
+        let metrics = extract(&[rule], "1 -> 2\n");
+        assert_eq!(
+            metrics,
+            IndexMap::from([("key".into(), 1.0), ("value".into(), 2.0)])
+        );
+    }
+
+    #[test]
+    fn key_value_pairs_with_a_separator_and_keys() {
+        let rule = MetricRule::new(MetricMode::KeyValue)
+            .separator(":")
+            .keys(&["rtt", "loss"]);
+        let metrics = extract(&[rule], "rtt: 1.5e-2 cwnd: 10\nloss:-0.5 name: bbr\n");
+        assert_eq!(
+            metrics,
+            IndexMap::from([("rtt".into(), 0.015), ("loss".into(), -0.5)])
+        );
+        let metrics = extract(&[MetricRule::new(MetricMode::KeyValue)], "tcp.cwnd = 10");
+        assert_eq!(metrics["tcp.cwnd"], 10.0);
+    }
+
+    #[test]
+    fn csv_reads_the_last_complete_row() {
+        let rule = MetricRule::new(MetricMode::Csv).delimiter(';');
+        let stdout = "starting\ntime; throughput; policy\n1; 10; bbr\n2; 12.5; bbr\n3; 13\n";
+        let metrics = extract(&[rule], stdout);
+        assert_eq!(
+            metrics,
+            IndexMap::from([("time".into(), 2.0), ("throughput".into(), 12.5)])
+        );
+        assert!(extract(&[MetricRule::new(MetricMode::Csv)], "no table").is_empty());
+    }
+
+    #[test]
+    fn json_lines_flatten_numeric_fields() {
+        let stdout = "{\"a\": 1, \"b\": {\"c\": 2, \"d\": \"x\"}}\n{broken\nlog line\n{\"a\": 3}\n";
+        let metrics = extract(&[MetricRule::new(MetricMode::JsonLines)], stdout);
+        assert_eq!(
+            metrics,
+            IndexMap::from([("a".into(), 3.0), ("b.c".into(), 2.0)])
+        );
+    }
+
+    #[test]
+    fn sources_and_required_metrics() {
+        let extractor = MetricExtractor::new(&[
+            MetricRule::regex(r"x=(?P<x>\d+)").source(MetricSource::Stderr),
+            MetricRule::regex(r"y=(?P<y>\d+)").required(&["y", "z"]),
+        ])
+        .unwrap();
+        assert!(matches!(
+            extractor.extract("x=1 y=2", "x=3"),
+            Err(Error::MissingMetric(missing)) if missing == "z"
+        ));
+        let extractor =
+            MetricExtractor::new(&[MetricRule::regex(r"x=(?P<x>\d+)").required(&["x"])]).unwrap();
+        assert_eq!(extractor.extract("", "x=3").ok(), None);
+        assert_eq!(extractor.extract("x=4", "x=3").unwrap()["x"], 4.0);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        assert!(matches!(
+            MetricExtractor::new(&[MetricRule::new(MetricMode::Regex)]),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            MetricExtractor::new(&[MetricRule::regex("(?P<x>")]),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,265 @@
+//! Dry-run plan of an executor
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::core::BuildCmd;
+use crate::error::Error;
+use crate::progress::format_duration;
+
+/// # TaskCommand
+///
+/// A command line exactly as the executor spawns it: the program, its arguments, the
+/// environment variables set on top of the inherited ones and the working directory it is run
+/// in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TaskCommand {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub cwd: PathBuf,
+}
+
+impl TaskCommand {
+    /// Command to build the ns-3 tree located at `ns3_dir`.
+    pub fn build(ns3_dir: impl AsRef<Path>) -> Self {
+        let ns3_dir = ns3_dir.as_ref();
+        TaskCommand {
+            program: ns3_dir.join("waf"),
+            args: vec!["build".to_string()],
+            env: vec![],
+            cwd: ns3_dir.to_path_buf(),
+        }
+    }
+
+    /// Command to run the program built by `BuildCmd::build_cmd` in the ns-3 tree located at
+    /// `ns3_dir`, without building it again.
+    pub fn run(ns3_dir: impl AsRef<Path>, argument: String) -> Self {
+        let ns3_dir = ns3_dir.as_ref();
+        TaskCommand {
+            program: ns3_dir.join("waf"),
+            args: vec!["--run-no-build".to_string(), argument],
+            env: vec![],
+            cwd: ns3_dir.to_path_buf(),
+        }
+    }
+
+    /// Make waf run the program in `work_dir` instead of the ns-3 directory.
+    pub fn with_work_dir(mut self, work_dir: impl AsRef<Path>) -> Self {
+        self.args
+            .push(format!("--cwd={}", work_dir.as_ref().display()));
+        self
+    }
+
+    /// The command without the working directory set by `with_work_dir`.
+    pub(crate) fn without_work_dir(&self) -> Self {
+        let mut command = self.clone();
+        command.args.retain(|a| !a.starts_with("--cwd="));
+        command
+    }
+
+    /// Make waf run the program through `template`, in which `%s` stands for the program and
+    /// its arguments, e.g. `gdb --args %s`. `%s` is appended when missing. A template set
+    /// before is replaced.
+    pub fn with_command_template(mut self, template: &str) -> Self {
+        let template = match template.contains("%s") {
+            true => template.to_string(),
+            false => format!("{} %s", template),
+        };
+        // waf ignores the arguments given with the program when there is a template, so they
+        // are moved into the template, with `%` escaped from waf's formatting.
+        let program_args = match self
+            .args
+            .iter()
+            .position(|a| a.starts_with("--command-template="))
+        {
+            Some(i) => {
+                let previous = self.args.remove(i);
+                previous
+                    .split_once("%s")
+                    .map(|(_, args)| args.to_string())
+                    .unwrap_or_default()
+            }
+            None => self
+                .args
+                .iter()
+                .position(|a| a == "--run-no-build")
+                .and_then(|i| self.args.get_mut(i + 1))
+                .and_then(|argument| {
+                    let (program, args) = argument.split_once(' ')?;
+                    let args = format!(" {}", args.replace('%', "%%"));
+                    *argument = program.to_string();
+                    Some(args)
+                })
+                .unwrap_or_default(),
+        };
+        self.args.push(format!(
+            "--command-template={}",
+            template.replacen("%s", &format!("%s{}", program_args), 1)
+        ));
+        self
+    }
+
+    /// Set the environment variable `key`, replacing its value when it is already set.
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.env.retain(|(k, _)| k != key);
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn to_command(&self) -> Command {
+        let mut command = Command::new(self.program.as_os_str());
+        command
+            .args(&self.args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .current_dir(&self.cwd);
+        command
+    }
+}
+
+impl fmt::Display for TaskCommand {
+    /// Render the command line the way it would be typed in a shell, working directory
+    /// excluded.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.env {
+            write!(f, "{}={} ", key, shell_quote(value))?;
+        }
+        write!(f, "{}", shell_quote(&self.program.display().to_string()))?;
+        for arg in &self.args {
+            write!(f, " {}", shell_quote(arg))?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn shell_quote(s: &str) -> String {
+    let safe = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if safe {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// A task of the plan, identified by its experiment and its index in the params returned by
+/// `BuildParam::build_param`.
+#[derive(Debug, Clone)]
+pub struct PlannedTask<P: BuildCmd> {
+    pub experiment: String,
+    pub index: usize,
+    pub param: P,
+    pub command: TaskCommand,
+    /// Estimated runtime of the task, see `LaunchOrder::LongestFirst`.
+    pub estimate: Option<Duration>,
+}
+
+/// # Plan
+///
+/// Everything `Executor::execute` would do, computed without building ns-3 or spawning any
+/// program. Get it with `Executor::plan`.
+#[derive(Debug, Clone)]
+pub struct Plan<P: BuildCmd> {
+    /// Command used to build ns-3 before launching the tasks.
+    pub build: TaskCommand,
+    /// Tasks in launch order.
+    pub tasks: Vec<PlannedTask<P>>,
+    /// Number of tasks of each experiment, in config file order.
+    pub counts: IndexMap<String, usize>,
+    /// Groups of tasks, as `(experiment, index)`, that share the same command.
+    pub duplicates: Vec<Vec<(String, usize)>>,
+}
+
+impl<P: BuildCmd> Plan<P> {
+    pub(crate) fn new(
+        build: TaskCommand,
+        tasks: Vec<PlannedTask<P>>,
+        experiments: impl Iterator<Item = String>,
+    ) -> Self {
+        let mut counts: IndexMap<String, usize> = experiments.map(|k| (k, 0)).collect();
+        let mut groups: IndexMap<&TaskCommand, Vec<(String, usize)>> = IndexMap::new();
+        for task in &tasks {
+            *counts.entry(task.experiment.clone()).or_default() += 1;
+            groups
+                .entry(&task.command)
+                .or_default()
+                .push((task.experiment.clone(), task.index));
+        }
+        let duplicates = groups.into_values().filter(|v| v.len() > 1).collect();
+        Plan {
+            build,
+            tasks,
+            counts,
+            duplicates,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Number of distinct commands, which is the number of runs when deduplication is on.
+    pub fn unique(&self) -> usize {
+        self.total() - self.duplicates.iter().map(|g| g.len() - 1).sum::<usize>()
+    }
+
+    /// Write the plan, as rendered by its `Display` impl, to a file.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut f = std::fs::File::create(path)?;
+        write!(f, "{}", self)?;
+        Ok(())
+    }
+}
+
+impl<P: BuildCmd> fmt::Display for Plan<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# Build")?;
+        writeln!(f, "cwd: {}", self.build.cwd.display())?;
+        writeln!(f, "{}", self.build)?;
+        writeln!(f)?;
+        writeln!(f, "# Experiments")?;
+        for (name, count) in &self.counts {
+            writeln!(f, "{}: {} tasks", name, count)?;
+        }
+        writeln!(
+            f,
+            "total: {} tasks, {} unique commands",
+            self.total(),
+            self.unique()
+        )?;
+        writeln!(f)?;
+        writeln!(f, "# Tasks")?;
+        for task in &self.tasks {
+            write!(
+                f,
+                "[{}#{}] cwd: {}",
+                task.experiment,
+                task.index,
+                task.command.cwd.display()
+            )?;
+            match task.estimate {
+                Some(estimate) => writeln!(f, " estimate: {}", format_duration(estimate))?,
+                None => writeln!(f)?,
+            }
+            writeln!(f, "{}", task.command)?;
+        }
+        if !self.duplicates.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "# Duplicates")?;
+            for group in &self.duplicates {
+                let ids: Vec<String> = group
+                    .iter()
+                    .map(|(name, index)| format!("{}#{}", name, index))
+                    .collect();
+                writeln!(f, "{}", ids.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//! Helpers on the process tree of a task
+//!
+//! Tasks are launched through waf, which spawns the ns-3 program as its own child, so most of
+//! the work of a task happens in descendants of the spawned process.
+
+/// Pids of all the descendants of `pid`, found through `/proc/<pid>/task/<tid>/children`.
+pub(crate) fn descendants(pid: u32) -> Vec<u32> {
+    let mut found = vec![];
+    let mut stack = vec![pid];
+    while let Some(pid) = stack.pop() {
+        let Ok(tasks) = std::fs::read_dir(format!("/proc/{}/task", pid)) else {
+            continue;
+        };
+        for task in tasks.flatten() {
+            let Ok(children) = std::fs::read_to_string(task.path().join("children")) else {
+                continue;
+            };
+            for child in children.split_whitespace().filter_map(|c| c.parse().ok()) {
+                found.push(child);
+                stack.push(child);
+            }
+        }
+    }
+    found
+}
+
+/// Kill every process in the process group `pgid` with `SIGKILL`.
+///
+/// Tasks are spawned as the leaders of their own group, which the ns-3 program launched by waf
+/// stays in even once waf died and it was reparented.
+pub(crate) fn kill_group(pgid: u32) {
+    // SAFETY: killpg has no memory safety requirements.
+    unsafe {
+        libc::killpg(pgid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+/// # GroupGuard
+///
+/// Kills the process group of a running task when dropped, so that dropping the future of the
+/// task, on timeout or along with the stream of `Executor::execute_stream`, does not leave its
+/// ns-3 program running. Disarmed once the task exited on its own.
+pub(crate) struct GroupGuard(Option<u32>);
+
+impl GroupGuard {
+    pub fn new(pgid: Option<u32>) -> Self {
+        GroupGuard(pgid)
+    }
+
+    pub fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        if let Some(pgid) = self.0 {
+            kill_group(pgid);
+        }
+    }
+}
+
+/// Sum of the peak resident set sizes, `VmHWM`, of `pid` and its descendants, in bytes.
+pub(crate) fn peak_rss(pid: u32) -> u64 {
+    let mut pids = descendants(pid);
+    pids.push(pid);
+    pids.into_iter()
+        .filter_map(|pid| {
+            let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+            let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+            let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+            Some(kb * 1024)
+        })
+        .sum()
+}
@@ -0,0 +1,508 @@
+//! Progress reporting
+
+use indexmap::IndexMap;
+use std::fmt::Debug;
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// # ProgressReporter
+///
+/// This trait is used to report the progress of `Executor::execute`.
+///
+/// Pass an implementation to `ExecutorBuilder::progress`. Every method has an empty default
+/// implementation, so only the events of interest need to be handled. Three implementations are
+/// provided: `MultiBarReporter` for terminals, `LogReporter` for logs and `SilentReporter`.
+///
+/// When a run is shared by several params through deduplication, `task_started` and
+/// `task_finished` are called once for each of them.
+pub trait ProgressReporter: Debug + Send + Sync {
+    /// Called before building ns-3.
+    fn build_started(&self) {}
+
+    /// Called after ns-3 was built successfully.
+    fn build_finished(&self, _elapsed: Duration) {}
+
+    /// Called before launching any task, with the number of tasks of each experiment.
+    fn tasks_planned(&self, _counts: &IndexMap<String, usize>) {}
+
+    /// Called after `tasks_planned` when the runtime of the tasks is estimated, with the sum of
+    /// the estimated runtimes of the tasks of each experiment.
+    fn work_planned(&self, _estimates: &IndexMap<String, Duration>) {}
+
+    fn task_started(&self, _experiment: &str) {}
+
+    /// Called right before `task_finished` for a task whose runtime was estimated, with its
+    /// estimate.
+    fn work_done(&self, _experiment: &str, _estimate: Duration) {}
+
+    fn task_finished(&self, _experiment: &str, _success: bool) {}
+
+    /// Called every second while tasks run, so the elapsed and remaining times are refreshed
+    /// even when no task finishes for a long time.
+    fn tick(&self) {}
+
+    /// Called once all tasks are finished, or when execution is aborted.
+    fn finished(&self) {}
+}
+
+/// Pick `MultiBarReporter` when stdout is a terminal, `LogReporter` otherwise.
+pub fn default_reporter() -> Arc<dyn ProgressReporter> {
+    if std::io::stdout().is_terminal() {
+        Arc::new(MultiBarReporter::new())
+    } else {
+        Arc::new(LogReporter::new(Duration::from_secs(30)))
+    }
+}
+
+/// Report nothing at all.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilentReporter;
+
+impl ProgressReporter for SilentReporter {}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Count {
+    total: usize,
+    started: usize,
+    done: usize,
+    failed: usize,
+    /// Sum of the estimated runtimes of the tasks, and of the finished ones.
+    work: Duration,
+    work_done: Duration,
+}
+
+/// Counters shared by the provided reporters.
+#[derive(Debug)]
+struct Counters {
+    experiments: IndexMap<String, Count>,
+    start: Instant,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Counters {
+            experiments: IndexMap::new(),
+            start: Instant::now(),
+        }
+    }
+
+    fn plan(&mut self, counts: &IndexMap<String, usize>) {
+        self.experiments = counts
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.to_owned(),
+                    Count {
+                        total: *v,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        self.start = Instant::now();
+    }
+
+    fn plan_work(&mut self, estimates: &IndexMap<String, Duration>) {
+        for (name, estimate) in estimates {
+            if let Some(c) = self.experiments.get_mut(name) {
+                c.work = *estimate;
+            }
+        }
+    }
+
+    fn finish_work(&mut self, experiment: &str, estimate: Duration) {
+        if let Some(c) = self.experiments.get_mut(experiment) {
+            c.work_done += estimate;
+        }
+    }
+
+    fn start(&mut self, experiment: &str) {
+        if let Some(c) = self.experiments.get_mut(experiment) {
+            c.started += 1;
+        }
+    }
+
+    fn finish(&mut self, experiment: &str, success: bool) {
+        if let Some(c) = self.experiments.get_mut(experiment) {
+            c.done += 1;
+            if !success {
+                c.failed += 1;
+            }
+        }
+    }
+
+    fn total(&self) -> Count {
+        self.experiments
+            .values()
+            .fold(Count::default(), |acc, c| Count {
+                total: acc.total + c.total,
+                started: acc.started + c.started,
+                done: acc.done + c.done,
+                failed: acc.failed + c.failed,
+                work: acc.work + c.work,
+                work_done: acc.work_done + c.work_done,
+            })
+    }
+
+    fn eta(&self, count: &Count) -> Option<Duration> {
+        remaining(count, self.start.elapsed())
+    }
+}
+
+/// Remaining time assuming the work left goes as fast as the work done in `elapsed`, measured
+/// in estimated runtime when the tasks have estimates, in tasks otherwise.
+fn remaining(count: &Count, elapsed: Duration) -> Option<Duration> {
+    if count.done == 0 {
+        return None;
+    }
+    let (done, total) = if count.work_done > Duration::ZERO {
+        (count.work_done.as_secs_f64(), count.work.as_secs_f64())
+    } else {
+        (count.done as f64, count.total as f64)
+    };
+    Some(elapsed.mul_f64((total - done).max(0.0) / done))
+}
+
+pub(crate) fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn format_eta(eta: Option<Duration>) -> String {
+    eta.map(format_duration)
+        .unwrap_or_else(|| "--:--:--".to_string())
+}
+
+/// # MultiBarReporter
+///
+/// Interactive progress bars, one for each experiment and one for the whole run, showing the
+/// number of finished and failed tasks and the estimated remaining time.
+///
+/// The bars are redrawn in place with ANSI escape codes, so this is meant for terminals. Use
+/// `LogReporter` when the output goes to a file or a CI log.
+#[derive(Debug)]
+pub struct MultiBarReporter {
+    state: Mutex<MultiBarState>,
+}
+
+#[derive(Debug)]
+struct MultiBarState {
+    counters: Counters,
+    drawn_lines: usize,
+    last_draw: Option<Instant>,
+}
+
+const BAR_WIDTH: usize = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+impl Default for MultiBarReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultiBarReporter {
+    pub fn new() -> Self {
+        MultiBarReporter {
+            state: Mutex::new(MultiBarState {
+                counters: Counters::new(),
+                drawn_lines: 0,
+                last_draw: None,
+            }),
+        }
+    }
+
+    fn line(name: &str, width: usize, count: &Count, eta: Option<Duration>) -> String {
+        let filled = (count.done * BAR_WIDTH)
+            .checked_div(count.total)
+            .unwrap_or(BAR_WIDTH);
+        format!(
+            "{:<width$} [{}{}] {}/{} failed: {} eta: {}",
+            name,
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            count.done,
+            count.total,
+            count.failed,
+            format_eta(eta),
+            width = width,
+        )
+    }
+
+    fn draw(state: &mut MultiBarState, force: bool) {
+        if !force
+            && state
+                .last_draw
+                .is_some_and(|t| t.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        let counters = &state.counters;
+        let width = counters
+            .experiments
+            .keys()
+            .map(|k| k.len())
+            .max()
+            .unwrap_or(0)
+            .max("total".len());
+        let mut out = String::new();
+        if state.drawn_lines > 0 {
+            out += &format!("\x1b[{}A", state.drawn_lines);
+        }
+        for (name, count) in &counters.experiments {
+            out += &format!(
+                "\r\x1b[2K{}\n",
+                Self::line(name, width, count, counters.eta(count))
+            );
+        }
+        let total = counters.total();
+        out += &format!(
+            "\r\x1b[2K{}\n",
+            Self::line("total", width, &total, counters.eta(&total))
+        );
+        state.drawn_lines = counters.experiments.len() + 1;
+        state.last_draw = Some(Instant::now());
+        let mut stdout = std::io::stdout().lock();
+        let _ = stdout.write_all(out.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+impl ProgressReporter for MultiBarReporter {
+    fn build_started(&self) {
+        println!("========== Build NS3 Program ==========");
+    }
+
+    fn build_finished(&self, elapsed: Duration) {
+        println!("Build NS3 Successfully in {}!", format_duration(elapsed));
+    }
+
+    fn tasks_planned(&self, counts: &IndexMap<String, usize>) {
+        println!("========== Execute NS3 Tasks ==========");
+        let mut state = self.state.lock().unwrap();
+        state.counters.plan(counts);
+        Self::draw(&mut state, true);
+    }
+
+    fn work_planned(&self, estimates: &IndexMap<String, Duration>) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.plan_work(estimates);
+    }
+
+    fn task_started(&self, experiment: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.start(experiment);
+    }
+
+    fn work_done(&self, experiment: &str, estimate: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.finish_work(experiment, estimate);
+    }
+
+    fn task_finished(&self, experiment: &str, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.finish(experiment, success);
+        Self::draw(&mut state, false);
+    }
+
+    fn tick(&self) {
+        let mut state = self.state.lock().unwrap();
+        // Nothing to draw before the tasks are planned.
+        if state.drawn_lines > 0 {
+            Self::draw(&mut state, false);
+        }
+    }
+
+    fn finished(&self) {
+        let mut state = self.state.lock().unwrap();
+        Self::draw(&mut state, true);
+    }
+}
+
+/// # LogReporter
+///
+/// Plain log lines without control characters, suitable for files and CI logs.
+///
+/// A line is printed when the build starts and finishes, then the progress of each experiment
+/// is printed once per `interval`, rounded up to the next tick, and a summary is printed at the
+/// end.
+#[derive(Debug)]
+pub struct LogReporter {
+    interval: Duration,
+    state: Mutex<LogState>,
+}
+
+#[derive(Debug)]
+struct LogState {
+    counters: Counters,
+    last_log: Instant,
+}
+
+impl LogReporter {
+    pub fn new(interval: Duration) -> Self {
+        LogReporter {
+            interval,
+            state: Mutex::new(LogState {
+                counters: Counters::new(),
+                last_log: Instant::now(),
+            }),
+        }
+    }
+
+    /// Progress of the run, `elapsed` after the tasks were planned.
+    fn progress_lines(counters: &Counters, elapsed: Duration) -> Vec<String> {
+        let total = counters.total();
+        let mut lines = vec![format!(
+            "[ns3-parallel] {}/{} tasks done, {} failed, {} running, elapsed: {}, eta: {}",
+            total.done,
+            total.total,
+            total.failed,
+            total.started.saturating_sub(total.done),
+            format_duration(elapsed),
+            format_eta(remaining(&total, elapsed)),
+        )];
+        for (name, count) in &counters.experiments {
+            lines.push(format!(
+                "[ns3-parallel]   {}: {}/{} done, {} failed",
+                name, count.done, count.total, count.failed
+            ));
+        }
+        lines
+    }
+
+    fn summary_line(counters: &Counters, elapsed: Duration) -> String {
+        let total = counters.total();
+        format!(
+            "[ns3-parallel] finished {}/{} tasks, {} failed, in {}",
+            total.done,
+            total.total,
+            total.failed,
+            format_duration(elapsed)
+        )
+    }
+}
+
+impl ProgressReporter for LogReporter {
+    fn build_started(&self) {
+        println!("[ns3-parallel] building ns-3");
+    }
+
+    fn build_finished(&self, elapsed: Duration) {
+        println!("[ns3-parallel] ns-3 built in {}", format_duration(elapsed));
+    }
+
+    fn tasks_planned(&self, counts: &IndexMap<String, usize>) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.plan(counts);
+        state.last_log = Instant::now();
+        println!(
+            "[ns3-parallel] launching {} tasks of {} experiments",
+            state.counters.total().total,
+            counts.len()
+        );
+    }
+
+    fn work_planned(&self, estimates: &IndexMap<String, Duration>) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.plan_work(estimates);
+    }
+
+    fn task_started(&self, experiment: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.start(experiment);
+    }
+
+    fn work_done(&self, experiment: &str, estimate: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.finish_work(experiment, estimate);
+    }
+
+    fn task_finished(&self, experiment: &str, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.counters.finish(experiment, success);
+    }
+
+    fn tick(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.counters.total().total > 0 && state.last_log.elapsed() >= self.interval {
+            state.last_log = Instant::now();
+            for line in Self::progress_lines(&state.counters, state.counters.start.elapsed()) {
+                println!("{}", line);
+            }
+        }
+    }
+
+    fn finished(&self) {
+        let state = self.state.lock().unwrap();
+        let counters = &state.counters;
+        println!("{}", Self::summary_line(counters, counters.start.elapsed()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(total: usize, done: usize) -> Count {
+        Count {
+            total,
+            done,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn remaining_time_follows_the_work_done() {
+        let elapsed = Duration::from_secs(60);
+        assert_eq!(remaining(&count(4, 0), elapsed), None);
+        assert_eq!(remaining(&count(4, 4), elapsed), Some(Duration::ZERO));
+        assert_eq!(
+            remaining(&count(4, 1), elapsed),
+            Some(Duration::from_secs(180))
+        );
+        // Estimated runtimes win over task counts: a quarter of the tasks but half of the work.
+        let estimated = Count {
+            work: Duration::from_secs(1000),
+            work_done: Duration::from_secs(500),
+            ..count(4, 1)
+        };
+        assert_eq!(remaining(&estimated, elapsed), Some(elapsed));
+        // Never negative, even when more work was done than planned.
+        let over = Count {
+            work: Duration::from_secs(10),
+            work_done: Duration::from_secs(20),
+            ..count(4, 2)
+        };
+        assert_eq!(remaining(&over, elapsed), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn log_lines() {
+        let mut counters = Counters::new();
+        counters.plan(&IndexMap::from([
+            ("a".to_string(), 2),
+            ("b".to_string(), 2),
+        ]));
+        counters.start("a");
+        counters.finish("a", true);
+        counters.start("b");
+        counters.finish("b", false);
+        counters.start("a");
+        let elapsed = Duration::from_secs(3725);
+        assert_eq!(
+            LogReporter::progress_lines(&counters, elapsed),
+            [
+                "[ns3-parallel] 2/4 tasks done, 1 failed, 1 running, elapsed: 01:02:05, eta: \
+                 01:02:05",
+                "[ns3-parallel]   a: 1/2 done, 0 failed",
+                "[ns3-parallel]   b: 1/2 done, 1 failed",
+            ]
+        );
+        assert_eq!(
+            LogReporter::summary_line(&counters, elapsed),
+            "[ns3-parallel] finished 2/4 tasks, 1 failed, in 01:02:05"
+        );
+        assert_eq!(format_eta(None), "--:--:--");
+    }
+}
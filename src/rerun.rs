@@ -0,0 +1,214 @@
+//! Re-run of a recorded task
+//!
+//! `Rerun` executes again the exact command of a task of an earlier campaign, taken from its
+//! manifest or from a `TaskError`, in the same ns-3 tree, working directory and environment.
+//! It can run the program under a wrapper such as a debugger, through waf's
+//! `--command-template`, or with `NS_LOG` set.
+//!
+//! The `ns3-parallel` binary exposes it as a subcommand:
+//!
+//! ```text
+//! ns3-parallel rerun out/manifest.json exp-1/3 --wrapper "gdb --args" --ns-log "TcpSocketBase=level_all"
+//! ```
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), ns3_parallel::error::Error> {
+//! use ns3_parallel::rerun::Rerun;
+//!
+//! let rerun = Rerun::from_manifest_file("out/manifest.json", "exp-1", 3)?
+//!     .ns_log("TcpSocketBase=level_all|prefix_time");
+//! let output = rerun.output().await?;
+//! println!("{}", String::from_utf8_lossy(&output.stderr));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+use std::process::{ExitStatus, Output};
+
+use crate::error::{Error, TaskError};
+use crate::manifest::{Manifest, Ns3Info};
+use crate::plan::TaskCommand;
+
+/// # Rerun
+///
+/// A recorded command, ready to be executed again.
+#[derive(Debug, Clone)]
+pub struct Rerun {
+    command: TaskCommand,
+    ns3: Option<Ns3Info>,
+}
+
+impl Rerun {
+    pub fn new(command: TaskCommand) -> Self {
+        Rerun { command, ns3: None }
+    }
+
+    /// The task of index `index` of `experiment` in `manifest`.
+    pub fn from_manifest(
+        manifest: &Manifest,
+        experiment: &str,
+        index: usize,
+    ) -> Result<Self, Error> {
+        let task = manifest.task(experiment, index).ok_or_else(|| {
+            Error::InvalidConfig(format!(
+                "No task {} of experiment {:?} in the manifest.",
+                index, experiment
+            ))
+        })?;
+        Ok(Rerun {
+            command: task.command.clone(),
+            ns3: Some(manifest.ns3.clone()),
+        })
+    }
+
+    pub fn from_manifest_file(
+        path: impl AsRef<Path>,
+        experiment: &str,
+        index: usize,
+    ) -> Result<Self, Error> {
+        Self::from_manifest(&Manifest::from_file(path)?, experiment, index)
+    }
+
+    /// The command of a failed task.
+    pub fn from_task_error(error: &TaskError) -> Self {
+        Self::new(error.command.clone())
+    }
+
+    /// Run the program under `wrapper`, e.g. `gdb --args` or `valgrind --leak-check=full`,
+    /// see `TaskCommand::with_command_template`.
+    pub fn wrapper(mut self, wrapper: &str) -> Self {
+        self.command = self.command.with_command_template(wrapper);
+        self
+    }
+
+    /// Set `NS_LOG`, e.g. `TcpSocketBase=level_all|prefix_time`, replacing the recorded value.
+    /// Logging is only compiled in debug builds of ns-3.
+    pub fn ns_log(mut self, ns_log: &str) -> Self {
+        self.command = self.command.with_env("NS_LOG", ns_log);
+        self
+    }
+
+    pub fn command(&self) -> &TaskCommand {
+        &self.command
+    }
+
+    /// Check that the ns-3 tree is still at the git commit recorded in the manifest, and has
+    /// no more local changes than it had then. Always `Ok` without a manifest.
+    pub fn check_ns3(&self) -> Result<(), Error> {
+        let Some(recorded) = &self.ns3 else {
+            return Ok(());
+        };
+        let current = Ns3Info::probe(Path::new(&recorded.path));
+        if current.git_commit != recorded.git_commit {
+            return Err(Error::InvalidConfig(format!(
+                "ns-3 tree {} is at commit {:?}, the manifest recorded {:?}.",
+                recorded.path, current.git_commit, recorded.git_commit
+            )));
+        }
+        if current.git_dirty == Some(true) && recorded.git_dirty == Some(false) {
+            return Err(Error::InvalidConfig(format!(
+                "ns-3 tree {} has local changes, the manifest recorded none.",
+                recorded.path
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run the command and capture its output.
+    pub async fn output(&self) -> Result<Output, Error> {
+        self.command.to_command().output().await.map_err(|e| {
+            Error::ExecuteFail(format!("Failed to execute NS3 program. Err: {:?}.", e))
+        })
+    }
+
+    /// Run the command attached to the terminal, as a debugger needs.
+    pub async fn status(&self) -> Result<ExitStatus, Error> {
+        self.command.to_command().status().await.map_err(|e| {
+            Error::ExecuteFail(format!("Failed to execute NS3 program. Err: {:?}.", e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ConfigFormat;
+    use crate::manifest::{HostInfo, ManifestTask};
+    use indexmap::IndexMap;
+
+    fn manifest(tasks: Vec<ManifestTask>) -> Manifest {
+        Manifest {
+            crate_version: "0.0.0".to_string(),
+            created_at: 0.0,
+            config_path: "config.toml".to_string(),
+            config_format: ConfigFormat::Toml,
+            config_sha256: String::new(),
+            configs: IndexMap::new(),
+            ns3: Ns3Info {
+                path: "/ns-3".to_string(),
+                git_commit: None,
+                git_dirty: None,
+                build_profile: None,
+            },
+            host: HostInfo {
+                hostname: "host".to_string(),
+                os: "linux".to_string(),
+                kernel: None,
+                arch: "x86_64".to_string(),
+                cpus: 1,
+            },
+            env: IndexMap::new(),
+            tasks,
+        }
+    }
+
+    #[test]
+    fn tasks_are_found_in_the_manifest() {
+        let command = TaskCommand::run("/ns-3", "simple-ns3".to_string());
+        let manifest = manifest(vec![ManifestTask {
+            experiment: "exp".to_string(),
+            index: 3,
+            command: command.clone(),
+            rng_seed: None,
+            rng_run: None,
+        }]);
+        let rerun = Rerun::from_manifest(&manifest, "exp", 3).unwrap();
+        assert_eq!(rerun.command(), &command);
+        for (experiment, index) in [("exp", 2), ("other", 3)] {
+            assert!(matches!(
+                Rerun::from_manifest(&manifest, experiment, index),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn wrapper_and_ns_log_are_composed() {
+        let command = TaskCommand::run("/ns-3", "simple-ns3 --rate=5%".to_string())
+            .with_env("NS_LOG", "Tcp=level_all")
+            .with_env("NS_GLOBAL_VALUE", "RngRun=2");
+        let rerun = Rerun::new(command)
+            .wrapper("valgrind")
+            .wrapper("gdb --args")
+            .ns_log("Udp=level_info");
+        let command = rerun.command();
+        assert_eq!(
+            command.args,
+            [
+                "--run-no-build",
+                "simple-ns3",
+                "--command-template=gdb --args %s --rate=5%%",
+            ]
+        );
+        assert_eq!(
+            command.env,
+            [
+                ("NS_GLOBAL_VALUE".to_string(), "RngRun=2".to_string()),
+                ("NS_LOG".to_string(), "Udp=level_info".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Parsers for the files written by ns-3 programs
+//!
+//! Files are usually located through the working directory of a `Task`, which is only specific
+//! to the task when `ExecutorBuilder::output_dir` is set.
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+
+pub mod ascii;
+pub mod flowmon;
+pub mod pcap;
+
+/// Addresses, ports and protocol number identifying a flow in one direction. Ports are 0 for
+/// protocols without them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FiveTuple {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    pub protocol: u8,
+    pub source_port: u16,
+    pub destination_port: u16,
+}
+
+impl FiveTuple {
+    /// The same flow in the other direction.
+    pub fn reverse(&self) -> FiveTuple {
+        FiveTuple {
+            source: self.destination,
+            destination: self.source,
+            protocol: self.protocol,
+            source_port: self.destination_port,
+            destination_port: self.source_port,
+        }
+    }
+}
+
+/// Files directly in `dir` with the extension `extension`, sorted by name.
+fn find_by_extension(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, Error> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == extension) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Parse every file of `files` on its own, by file name.
+fn parse_each<T>(
+    files: Vec<PathBuf>,
+    mut parse: impl FnMut(&Path) -> Result<T, Error>,
+) -> Result<IndexMap<String, T>, Error> {
+    let mut parsed = IndexMap::new();
+    for path in files {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        parsed.insert(name, parse(&path)?);
+    }
+    Ok(parsed)
+}
@@ -0,0 +1,414 @@
+//! ASCII traces
+//!
+//! Parse the `.tr` files written by `AsciiTraceHelper`, line by line, into typed events, and
+//! summarize them per device: queue occupancy, drops and received throughput.
+//!
+//! ## Example
+//!
+//! ```
+//! use ns3_parallel::results::ascii::{AsciiTraceReader, TraceKind};
+//!
+//! let trace = "+ 2 /NodeList/0/DeviceList/1/$ns3::PointToPointNetDevice/TxQueue/Enqueue \
+//!     ns3::PppHeader (Point-to-Point Protocol: IP (0x0021)) ns3::Ipv4Header (tos 0x0 ttl 64 \
+//!     id 0 protocol 17 offset (bytes) 0 flags [none] length: 1052 10.1.1.1 > 10.1.1.2) \
+//!     ns3::UdpHeader (length: 1032 49153 > 9) Payload (size=1024)\n";
+//! let event = AsciiTraceReader::new(trace.as_bytes()).next().unwrap().unwrap();
+//! assert_eq!(event.kind, TraceKind::Enqueue);
+//! assert_eq!((event.node, event.device), (Some(0), Some(1)));
+//! assert_eq!(event.size, Some(1052));
+//! assert_eq!(event.headers, ["ns3::PppHeader", "ns3::Ipv4Header", "ns3::UdpHeader"]);
+//! ```
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::core::BuildCmd;
+use crate::error::Error;
+use crate::executor::Task;
+use crate::results::{find_by_extension, parse_each};
+
+/// Kind of a trace event, from the first character of its line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TraceKind {
+    /// `+`: the packet was put in a transmit queue.
+    Enqueue,
+    /// `-`: the packet left a transmit queue.
+    Dequeue,
+    /// `d`: the packet was dropped.
+    Drop,
+    /// `r`: the packet was received by a net device.
+    Receive,
+    /// `t`: the packet was transmitted, used by some devices such as Wi-Fi.
+    Transmit,
+}
+
+/// # TraceEvent
+///
+/// A line of an ASCII trace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceEvent {
+    pub kind: TraceKind,
+    pub time: Duration,
+    /// Config path of the trace source, e.g.
+    /// `/NodeList/0/DeviceList/1/$ns3::PointToPointNetDevice/TxQueue/Enqueue`.
+    pub context: String,
+    pub node: Option<u32>,
+    pub device: Option<u32>,
+    /// Size of the IP packet when there is an IP header, otherwise the size of the payload.
+    pub size: Option<u32>,
+    /// Names of the headers of the packet, outermost first.
+    pub headers: Vec<String>,
+    /// The packet as printed by ns-3.
+    pub packet: String,
+}
+
+impl TraceEvent {
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let invalid = || Error::ParseFail(format!("Invalid ASCII trace line: {:?}.", line));
+        let mut fields = line.splitn(4, ' ');
+        let kind = match fields.next() {
+            Some("+") => TraceKind::Enqueue,
+            Some("-") => TraceKind::Dequeue,
+            Some("d") => TraceKind::Drop,
+            Some("r") => TraceKind::Receive,
+            Some("t") => TraceKind::Transmit,
+            _ => return Err(invalid()),
+        };
+        let time = fields
+            .next()
+            .and_then(|t| t.parse::<f64>().ok())
+            .and_then(|t| Duration::try_from_secs_f64(t).ok())
+            .ok_or_else(invalid)?;
+        let context = fields.next().ok_or_else(invalid)?.to_string();
+        let packet = fields.next().unwrap_or_default().to_string();
+        Ok(TraceEvent {
+            kind,
+            time,
+            node: path_index(&context, "/NodeList/"),
+            device: path_index(&context, "/DeviceList/"),
+            size: packet_size(&packet),
+            headers: headers(&packet),
+            context,
+            packet,
+        })
+    }
+}
+
+/// The number following `segment` in a config path.
+fn path_index(context: &str, segment: &str) -> Option<u32> {
+    let start = context.find(segment)? + segment.len();
+    let rest = &context[start..];
+    rest[..rest.find('/').unwrap_or(rest.len())].parse().ok()
+}
+
+/// Names of the top level `ns3::` items of a printed packet.
+fn headers(packet: &str) -> Vec<String> {
+    let mut headers = vec![];
+    let mut depth = 0;
+    for word in packet.split(' ') {
+        if depth == 0 && word.starts_with("ns3::") {
+            headers.push(word.to_string());
+        }
+        depth += word.matches('(').count() as i32 - word.matches(')').count() as i32;
+    }
+    headers
+}
+
+/// Text between the parentheses following `name` in a printed packet.
+fn group<'a>(packet: &'a str, name: &str) -> Option<&'a str> {
+    let start = packet.find(name)? + name.len();
+    let rest = packet[start..].strip_prefix(" (")?;
+    let mut depth = 1;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return Some(&rest[..i]);
+        }
+    }
+    None
+}
+
+/// The number following `label` in `text`.
+fn number_after(text: &str, label: &str) -> Option<u32> {
+    let start = text.find(label)? + label.len();
+    let digits: String = text[start..]
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+fn packet_size(packet: &str) -> Option<u32> {
+    if let Some(ipv4) = group(packet, "ns3::Ipv4Header") {
+        return number_after(ipv4, "length:");
+    }
+    if let Some(ipv6) = group(packet, "ns3::Ipv6Header") {
+        return number_after(ipv6, "Payload Length").map(|l| l + 40);
+    }
+    number_after(packet, "Payload (size=")
+}
+
+/// # AsciiTraceReader
+///
+/// Iterator over the events of an ASCII trace, reading one line at a time. Empty lines are
+/// skipped.
+#[derive(Debug)]
+pub struct AsciiTraceReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+}
+
+impl AsciiTraceReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> AsciiTraceReader<R> {
+    pub fn new(reader: R) -> Self {
+        AsciiTraceReader {
+            reader,
+            line: String::new(),
+            line_number: 0,
+        }
+    }
+
+    fn read_event(&mut self) -> Result<Option<TraceEvent>, Error> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            let line = self.line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            return TraceEvent::parse(line).map(Some).map_err(|e| match e {
+                Error::ParseFail(message) => {
+                    Error::ParseFail(format!("line {}: {}", self.line_number, message))
+                }
+                e => e,
+            });
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for AsciiTraceReader<R> {
+    type Item = Result<TraceEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_event().transpose()
+    }
+}
+
+/// A net device, identified by its node and its index on the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DeviceId {
+    pub node: u32,
+    pub device: u32,
+}
+
+/// # DeviceSummary
+///
+/// What the ASCII trace shows of a net device.
+///
+/// The queue occupancy counts the packets enqueued and not yet dequeued, starting from an
+/// empty queue, so the dequeues of a trace started mid-run do not take it below zero. Drops do
+/// not change it, as the transmit queue reports the packets it refuses as drops.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceSummary {
+    pub enqueued: u64,
+    pub dequeued: u64,
+    pub drops: u64,
+    pub received_packets: u64,
+    pub received_bytes: u64,
+    /// Occupancy of the transmit queue, in packets, after each enqueue and dequeue.
+    pub queue: Vec<(Duration, u64)>,
+    pub max_queue: u64,
+    /// Received bytes in each bin, from the bin of the first event of the device.
+    pub received_series: Vec<(Duration, u64)>,
+    /// Width of the bins of `received_series`.
+    pub bin: Duration,
+}
+
+impl DeviceSummary {
+    /// Received bits per second in each bin of `received_series`.
+    pub fn throughput(&self) -> Vec<(Duration, f64)> {
+        let bin = self.bin.as_secs_f64();
+        self.received_series
+            .iter()
+            .map(|(start, bytes)| (*start, *bytes as f64 * 8.0 / bin))
+            .collect()
+    }
+
+    fn add(&mut self, event: &TraceEvent) {
+        // Counted from the previous occupancy rather than the enqueues and dequeues, which a
+        // trace started mid-run does not pair up.
+        let occupancy = self.queue.last().map_or(0, |(_, occupancy)| *occupancy);
+        match event.kind {
+            TraceKind::Enqueue => {
+                self.enqueued += 1;
+                self.queue.push((event.time, occupancy + 1));
+                self.max_queue = self.max_queue.max(occupancy + 1);
+            }
+            TraceKind::Dequeue => {
+                self.dequeued += 1;
+                self.queue.push((event.time, occupancy.saturating_sub(1)));
+            }
+            TraceKind::Drop => self.drops += 1,
+            TraceKind::Receive => {
+                let size = event.size.unwrap_or_default() as u64;
+                self.received_packets += 1;
+                self.received_bytes += size;
+                let bin = self.bin.as_nanos();
+                let index = event.time.as_nanos() / bin;
+                let first = match self.received_series.first() {
+                    Some((start, _)) => start.as_nanos() / bin,
+                    None => index,
+                };
+                let position = index.saturating_sub(first);
+                while self.received_series.len() as u128 <= position {
+                    let start = (first + self.received_series.len() as u128) * bin;
+                    self.received_series
+                        .push((Duration::from_nanos(start as u64), 0));
+                }
+                self.received_series[position as usize].1 += size;
+            }
+            TraceKind::Transmit => {}
+        }
+    }
+}
+
+/// Summarize `events` per device, with received series of width `bin`. Events whose context
+/// does not name a node and a device are skipped.
+pub fn summarize(
+    events: impl IntoIterator<Item = Result<TraceEvent, Error>>,
+    bin: Duration,
+) -> Result<IndexMap<DeviceId, DeviceSummary>, Error> {
+    let bin = bin.max(Duration::from_nanos(1));
+    let mut devices: IndexMap<DeviceId, DeviceSummary> = IndexMap::new();
+    for event in events {
+        let event = event?;
+        let (Some(node), Some(device)) = (event.node, event.device) else {
+            continue;
+        };
+        devices
+            .entry(DeviceId { node, device })
+            .or_insert_with(|| DeviceSummary {
+                bin,
+                ..Default::default()
+            })
+            .add(&event);
+    }
+    devices.sort_keys();
+    Ok(devices)
+}
+
+/// Drops of each device, without building the rest of the summary.
+pub fn drop_counts(
+    events: impl IntoIterator<Item = Result<TraceEvent, Error>>,
+) -> Result<IndexMap<DeviceId, u64>, Error> {
+    let mut drops: IndexMap<DeviceId, u64> = IndexMap::new();
+    for event in events {
+        let event = event?;
+        if let (TraceKind::Drop, Some(node), Some(device)) = (event.kind, event.node, event.device)
+        {
+            *drops.entry(DeviceId { node, device }).or_default() += 1;
+        }
+    }
+    drops.sort_keys();
+    Ok(drops)
+}
+
+/// ASCII trace files directly in `dir`, sorted by name.
+pub fn find(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    find_by_extension(dir.as_ref(), "tr")
+}
+
+/// Summarize every ASCII trace file in the working directory of a task, by file name.
+pub fn from_task<P: BuildCmd, O>(
+    task: &Task<P, O>,
+    bin: Duration,
+) -> Result<IndexMap<String, IndexMap<DeviceId, DeviceSummary>>, Error> {
+    parse_each(find(&task.work_dir)?, |path| {
+        summarize(AsciiTraceReader::open(path)?, bin)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TX: &str = "/NodeList/0/DeviceList/1/$ns3::PointToPointNetDevice/TxQueue";
+    const RX: &str = "/NodeList/1/DeviceList/1/$ns3::PointToPointNetDevice/MacRx";
+    const PACKET: &str = "ns3::PppHeader (Point-to-Point Protocol: IP (0x0021)) \
+        ns3::Ipv4Header (tos 0x0 ttl 64 id 0 protocol 17 offset (bytes) 0 flags [none] \
+        length: 1052 10.1.1.1 > 10.1.1.2) ns3::UdpHeader (length: 1032 49153 > 9) \
+        Payload (size=1024)";
+
+    fn summary(trace: &str) -> IndexMap<DeviceId, DeviceSummary> {
+        summarize(
+            AsciiTraceReader::new(trace.as_bytes()),
+            Duration::from_secs(1),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn trace_starting_with_dequeues() {
+        let trace = format!(
+            "- 1 {tx}/Dequeue {p}\n- 1.1 {tx}/Dequeue {p}\n+ 1.2 {tx}/Enqueue {p}\n\
+             + 1.3 {tx}/Enqueue {p}\n- 1.4 {tx}/Dequeue {p}\n",
+            tx = TX,
+            p = PACKET
+        );
+        let summaries = summary(&trace);
+        let device = &summaries[&DeviceId { node: 0, device: 1 }];
+        assert_eq!((device.enqueued, device.dequeued), (2, 3));
+        let occupancy: Vec<u64> = device.queue.iter().map(|(_, q)| *q).collect();
+        assert_eq!(occupancy, [0, 0, 1, 2, 1]);
+        assert_eq!(device.max_queue, 2);
+    }
+
+    #[test]
+    fn received_bytes_are_binned() {
+        let trace = format!(
+            "r 1.5 {rx} {p}\nr 2.5 {rx} {p}\n\nr 4.2 {rx} {p}\nd 4.3 {tx}/Drop {p}\n",
+            rx = RX,
+            tx = TX,
+            p = PACKET
+        );
+        let summaries = summary(&trace);
+        let receiver = &summaries[&DeviceId { node: 1, device: 1 }];
+        assert_eq!(receiver.received_packets, 3);
+        assert_eq!(receiver.received_bytes, 3 * 1052);
+        let series: Vec<u64> = receiver.received_series.iter().map(|(_, b)| *b).collect();
+        assert_eq!(series, [1052, 1052, 0, 1052]);
+        assert_eq!(receiver.received_series[0].0, Duration::from_secs(1));
+        assert_eq!(summaries[&DeviceId { node: 0, device: 1 }].drops, 1);
+    }
+
+    #[test]
+    fn malformed_lines_report_their_number() {
+        let trace = format!("+ 1 {}/Enqueue {}\nx 2 {}\n", TX, PACKET, TX);
+        let events: Vec<_> = AsciiTraceReader::new(trace.as_bytes()).collect();
+        assert!(events[0].is_ok());
+        match &events[1] {
+            Err(Error::ParseFail(message)) => assert!(message.starts_with("line 2:")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(TraceEvent::parse("+ soon /NodeList/0").is_err());
+        assert!(TraceEvent::parse("+ 1").is_err());
+    }
+}
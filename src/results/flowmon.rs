@@ -0,0 +1,393 @@
+//! FlowMonitor XML output
+//!
+//! Parse the file written by `FlowMonitor::SerializeToXmlFile` into typed per-flow statistics,
+//! joined with the 5-tuples of the IPv4 and IPv6 flow classifiers.
+//!
+//! ## Example
+//!
+//! ```
+//! use ns3_parallel::results::flowmon::FlowMonitorReport;
+//!
+//! let xml = r#"<?xml version="1.0" ?>
+//! <FlowMonitor>
+//!   <FlowStats>
+//!     <Flow flowId="1" timeFirstTxPacket="+1e+09ns" timeFirstRxPacket="+1e+09ns"
+//!       timeLastTxPacket="+2e+09ns" timeLastRxPacket="+2e+09ns" delaySum="+2e+07ns"
+//!       jitterSum="+0ns" lastDelay="+2e+06ns" txBytes="10000" rxBytes="9000" txPackets="10"
+//!       rxPackets="9" lostPackets="1" timesForwarded="9">
+//!     </Flow>
+//!   </FlowStats>
+//!   <Ipv4FlowClassifier>
+//!     <Flow flowId="1" sourceAddress="10.1.1.1" destinationAddress="10.1.1.2" protocol="17"
+//!       sourcePort="49153" destinationPort="9" />
+//!   </Ipv4FlowClassifier>
+//! </FlowMonitor>"#;
+//! let report = FlowMonitorReport::parse(xml).unwrap();
+//! let flow = report.flow(1).unwrap();
+//! assert_eq!(flow.five_tuple.as_ref().unwrap().destination_port, 9);
+//! assert_eq!(flow.stats.throughput(), Some(72000.0));
+//! assert_eq!(flow.stats.loss_rate(), Some(0.1));
+//! ```
+
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::core::BuildCmd;
+use crate::error::Error;
+use crate::executor::Task;
+use crate::results::find_by_extension;
+pub use crate::results::FiveTuple;
+
+/// # FlowMonitorReport
+///
+/// Every flow of a FlowMonitor XML file, ordered by flow id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowMonitorReport {
+    pub flows: Vec<Flow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Flow {
+    pub id: u32,
+    /// `None` when the flow is missing from the classifiers.
+    pub five_tuple: Option<FiveTuple>,
+    pub stats: FlowStats,
+}
+
+/// Statistics of a flow, as kept by `FlowMonitor::FlowStats`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlowStats {
+    pub time_first_tx_packet: Duration,
+    pub time_first_rx_packet: Duration,
+    pub time_last_tx_packet: Duration,
+    pub time_last_rx_packet: Duration,
+    pub delay_sum: Duration,
+    pub jitter_sum: Duration,
+    pub last_delay: Duration,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+    pub tx_packets: u64,
+    pub rx_packets: u64,
+    pub lost_packets: u64,
+    pub times_forwarded: u64,
+    pub delay_histogram: Histogram,
+    pub jitter_histogram: Histogram,
+    pub packet_size_histogram: Histogram,
+    pub flow_interruptions_histogram: Histogram,
+    /// Number of packets dropped for each reason code.
+    pub packets_dropped: Vec<(u32, u64)>,
+    /// Number of bytes dropped for each reason code.
+    pub bytes_dropped: Vec<(u32, u64)>,
+}
+
+/// Non-empty bins of a FlowMonitor histogram. `start` and `width` are in seconds for delays
+/// and jitters, and in bytes for packet sizes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Histogram {
+    pub bins: Vec<Bin>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bin {
+    pub index: u32,
+    pub start: f64,
+    pub width: f64,
+    pub count: u64,
+}
+
+impl FlowStats {
+    /// Received bits per second between the first and the last received packet.
+    pub fn throughput(&self) -> Option<f64> {
+        let duration = self
+            .time_last_rx_packet
+            .checked_sub(self.time_first_rx_packet)?
+            .as_secs_f64();
+        (duration > 0.0).then(|| self.rx_bytes as f64 * 8.0 / duration)
+    }
+
+    /// Fraction of the transmitted packets that were lost.
+    pub fn loss_rate(&self) -> Option<f64> {
+        (self.tx_packets > 0).then(|| self.lost_packets as f64 / self.tx_packets as f64)
+    }
+
+    pub fn mean_delay(&self) -> Option<Duration> {
+        (self.rx_packets > 0).then(|| self.delay_sum.div_f64(self.rx_packets as f64))
+    }
+
+    pub fn mean_jitter(&self) -> Option<Duration> {
+        (self.rx_packets > 1).then(|| self.jitter_sum.div_f64((self.rx_packets - 1) as f64))
+    }
+}
+
+impl FlowMonitorReport {
+    pub fn parse(xml: &str) -> Result<Self, Error> {
+        let doc = Document::parse(xml)?;
+        let root = doc.root_element();
+        if !root.has_tag_name("FlowMonitor") {
+            return Err(Error::ParseFail(format!(
+                "Expected a FlowMonitor document, found <{}>.",
+                root.tag_name().name()
+            )));
+        }
+        let mut flows = vec![];
+        for node in children(root, "FlowStats").flat_map(|n| children(n, "Flow")) {
+            flows.push(Flow {
+                id: attr(node, "flowId")?,
+                five_tuple: None,
+                stats: parse_stats(node)?,
+            });
+        }
+        let classifiers = children(root, "Ipv4FlowClassifier")
+            .chain(children(root, "Ipv6FlowClassifier"))
+            .flat_map(|n| children(n, "Flow"));
+        for node in classifiers {
+            let id: u32 = attr(node, "flowId")?;
+            let five_tuple = FiveTuple {
+                source: attr(node, "sourceAddress")?,
+                destination: attr(node, "destinationAddress")?,
+                protocol: attr(node, "protocol")?,
+                source_port: attr(node, "sourcePort")?,
+                destination_port: attr(node, "destinationPort")?,
+            };
+            if let Some(flow) = flows.iter_mut().find(|f| f.id == id) {
+                flow.five_tuple = Some(five_tuple);
+            }
+        }
+        flows.sort_by_key(|f| f.id);
+        Ok(FlowMonitorReport { flows })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse the FlowMonitor XML file written by a task, which must be the only one in its
+    /// working directory.
+    pub fn from_task<P: BuildCmd, O>(task: &Task<P, O>) -> Result<Self, Error> {
+        let files = find(&task.work_dir)?;
+        match files.as_slice() {
+            [file] => Self::from_file(file),
+            _ => Err(Error::FileNotFound(format!(
+                "Expected one FlowMonitor XML file in {:?}, found {}.",
+                task.work_dir,
+                files.len()
+            ))),
+        }
+    }
+
+    pub fn flow(&self, id: u32) -> Option<&Flow> {
+        self.flows.iter().find(|f| f.id == id)
+    }
+}
+
+/// FlowMonitor XML files directly in `dir`, sorted by name.
+pub fn find(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    let mut files = vec![];
+    for path in find_by_extension(dir.as_ref(), "xml")? {
+        if is_flowmon(&path)? {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Whether the root element of the XML file at `path` is `FlowMonitor`, judging from its
+/// first bytes.
+fn is_flowmon(path: &Path) -> Result<bool, Error> {
+    let mut head = vec![0; 256];
+    let n = std::fs::File::open(path)?.read(&mut head)?;
+    Ok(String::from_utf8_lossy(&head[..n]).contains("<FlowMonitor"))
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    tag: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.has_tag_name(tag))
+}
+
+fn attr<T: FromStr>(node: Node, name: &str) -> Result<T, Error> {
+    let value = node.attribute(name).ok_or_else(|| {
+        Error::ParseFail(format!(
+            "Missing attribute {} in <{}>.",
+            name,
+            node.tag_name().name()
+        ))
+    })?;
+    value.parse().map_err(|_| {
+        Error::ParseFail(format!(
+            "Invalid value {:?} of attribute {} in <{}>.",
+            value,
+            name,
+            node.tag_name().name()
+        ))
+    })
+}
+
+fn time_attr(node: Node, name: &str) -> Result<Duration, Error> {
+    let value: String = attr(node, name)?;
+    parse_time(&value).ok_or_else(|| {
+        Error::ParseFail(format!(
+            "Invalid time {:?} of attribute {} in <{}>.",
+            value,
+            name,
+            node.tag_name().name()
+        ))
+    })
+}
+
+/// Parse a time printed by ns-3, such as `+1.5e+09ns`.
+fn parse_time(value: &str) -> Option<Duration> {
+    let split = value
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .len();
+    let (number, unit) = value.split_at(split);
+    let scale = match unit {
+        "y" => 365.0 * 86400.0,
+        "d" => 86400.0,
+        "h" => 3600.0,
+        "min" => 60.0,
+        "s" | "" => 1.0,
+        "ms" => 1e-3,
+        "us" => 1e-6,
+        "ns" => 1e-9,
+        "ps" => 1e-12,
+        "fs" => 1e-15,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Duration::try_from_secs_f64(number * scale).ok()
+}
+
+fn parse_stats(node: Node) -> Result<FlowStats, Error> {
+    let histogram = |tag| -> Result<Histogram, Error> {
+        let mut bins = vec![];
+        for bin in children(node, tag).flat_map(|n| children(n, "bin")) {
+            bins.push(Bin {
+                index: attr(bin, "index")?,
+                start: attr(bin, "start")?,
+                width: attr(bin, "width")?,
+                count: attr(bin, "count")?,
+            });
+        }
+        Ok(Histogram { bins })
+    };
+    let dropped = |tag, field| -> Result<Vec<(u32, u64)>, Error> {
+        children(node, tag)
+            .map(|n| Ok((attr(n, "reasonCode")?, attr(n, field)?)))
+            .collect()
+    };
+    Ok(FlowStats {
+        time_first_tx_packet: time_attr(node, "timeFirstTxPacket")?,
+        time_first_rx_packet: time_attr(node, "timeFirstRxPacket")?,
+        time_last_tx_packet: time_attr(node, "timeLastTxPacket")?,
+        time_last_rx_packet: time_attr(node, "timeLastRxPacket")?,
+        delay_sum: time_attr(node, "delaySum")?,
+        jitter_sum: time_attr(node, "jitterSum")?,
+        last_delay: time_attr(node, "lastDelay")?,
+        tx_bytes: attr(node, "txBytes")?,
+        rx_bytes: attr(node, "rxBytes")?,
+        tx_packets: attr(node, "txPackets")?,
+        rx_packets: attr(node, "rxPackets")?,
+        lost_packets: attr(node, "lostPackets")?,
+        times_forwarded: attr(node, "timesForwarded")?,
+        delay_histogram: histogram("delayHistogram")?,
+        jitter_histogram: histogram("jitterHistogram")?,
+        packet_size_histogram: histogram("packetSizeHistogram")?,
+        flow_interruptions_histogram: histogram("flowInterruptionsHistogram")?,
+        packets_dropped: dropped("packetsDropped", "number")?,
+        bytes_dropped: dropped("bytesDropped", "bytes")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    const FLOW: &str = r#"timeFirstTxPacket="+1e+09ns" timeFirstRxPacket="+1.01e+09ns"
+        timeLastTxPacket="+2e+09ns" timeLastRxPacket="+2.01e+09ns" delaySum="+30ms"
+        jitterSum="+2ms" lastDelay="+10ms" txBytes="3000" rxBytes="2000" txPackets="3"
+        rxPackets="3" lostPackets="0" timesForwarded="0""#;
+
+    fn report(stats: &str, classifiers: &str) -> Result<FlowMonitorReport, Error> {
+        FlowMonitorReport::parse(&format!(
+            "<FlowMonitor><FlowStats>{}</FlowStats>{}</FlowMonitor>",
+            stats, classifiers
+        ))
+    }
+
+    #[test]
+    fn flows_are_joined_with_both_classifiers() {
+        let stats = format!(
+            r#"<Flow flowId="2" {flow}>
+                 <delayHistogram nBins="1">
+                   <bin index="1" start="0.01" width="0.01" count="3"/>
+                 </delayHistogram>
+                 <packetsDropped reasonCode="3" number="2"/>
+                 <bytesDropped reasonCode="3" bytes="1000"/>
+               </Flow>
+               <Flow flowId="1" {flow}/>"#,
+            flow = FLOW
+        );
+        let classifiers = r#"
+            <Ipv6FlowClassifier>
+              <Flow flowId="2" sourceAddress="2001:db8::1" destinationAddress="2001:db8::2"
+                protocol="6" sourcePort="49153" destinationPort="80"/>
+            </Ipv6FlowClassifier>"#;
+        let report = report(&stats, classifiers).unwrap();
+        assert_eq!(
+            report.flows.iter().map(|f| f.id).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert!(report.flow(1).unwrap().five_tuple.is_none());
+        let flow = report.flow(2).unwrap();
+        let five_tuple = flow.five_tuple.as_ref().unwrap();
+        assert_eq!(five_tuple.source, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(five_tuple.protocol, 6);
+        assert_eq!(flow.stats.delay_histogram.bins[0].count, 3);
+        assert!(flow.stats.jitter_histogram.bins.is_empty());
+        assert_eq!(flow.stats.packets_dropped, [(3, 2)]);
+        assert_eq!(flow.stats.bytes_dropped, [(3, 1000)]);
+        assert_eq!(flow.stats.mean_delay(), Some(Duration::from_millis(10)));
+        assert_eq!(flow.stats.mean_jitter(), Some(Duration::from_millis(1)));
+        assert_eq!(flow.stats.throughput(), Some(16000.0));
+    }
+
+    #[test]
+    fn times_keep_their_unit() {
+        assert_eq!(parse_time("+1.5e+09ns"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_time("+2min"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_time("3"), Some(Duration::from_secs(3)));
+        assert_eq!(parse_time("-1ns"), None);
+        assert_eq!(parse_time("1parsec"), None);
+        assert_eq!(parse_time("ns"), None);
+    }
+
+    #[test]
+    fn malformed_documents_are_rejected() {
+        assert!(FlowMonitorReport::parse("<FlowMonitor>").is_err());
+        assert!(matches!(
+            FlowMonitorReport::parse("<FlowStats/>"),
+            Err(Error::ParseFail(_))
+        ));
+        let missing = FLOW.replace(r#"txBytes="3000""#, "");
+        assert!(matches!(
+            report(&format!(r#"<Flow flowId="1" {}/>"#, missing), ""),
+            Err(Error::ParseFail(message)) if message.contains("txBytes")
+        ));
+        let bad_time = FLOW.replace("+30ms", "soon");
+        assert!(matches!(
+            report(&format!(r#"<Flow flowId="1" {}/>"#, bad_time), ""),
+            Err(Error::ParseFail(message)) if message.contains("delaySum")
+        ));
+        let classifier = r#"<Ipv4FlowClassifier><Flow flowId="1" sourceAddress="10.1.1"
+            destinationAddress="10.1.1.2" protocol="17" sourcePort="1" destinationPort="9"/>
+            </Ipv4FlowClassifier>"#;
+        assert!(report(&format!(r#"<Flow flowId="1" {}/>"#, FLOW), classifier).is_err());
+    }
+}
@@ -0,0 +1,620 @@
+//! Pcap traces
+//!
+//! Read the classic libpcap files written by `PcapHelper`, decode the IP packets they hold and
+//! summarize them per flow: packet and byte time series, throughput and, for TCP, RTT samples.
+//!
+//! Only the link types used by the ns-3 net devices that carry IP are supported: Ethernet
+//! (CSMA, with or without LLC/SNAP), PPP (point-to-point) and raw IP. pcapng files are not
+//! supported.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use ns3_parallel::results::pcap;
+//! use std::time::Duration;
+//!
+//! let flows = pcap::summarize_file("out/exp/0/trace-0-1.pcap", Duration::from_millis(100)).unwrap();
+//! for flow in flows {
+//!     println!("{:?}: {:?} bps, {:?} rtt", flow.five_tuple, flow.throughput(), flow.mean_rtt());
+//! }
+//! ```
+
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::core::BuildCmd;
+use crate::error::Error;
+use crate::executor::Task;
+use crate::results::{find_by_extension, parse_each, FiveTuple};
+
+/// Largest record accepted whatever the snapshot length of the file says, far above the frames
+/// of ns-3 devices, so a corrupt length can not make the reader allocate gigabytes.
+const MAX_RECORD_LEN: u32 = 256 * 1024;
+
+/// Link type of a pcap file, from the `network` field of its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkType {
+    /// `LINKTYPE_ETHERNET`, written by CSMA devices.
+    Ethernet,
+    /// `LINKTYPE_PPP`, written by point-to-point devices.
+    Ppp,
+    /// `LINKTYPE_RAW`, `LINKTYPE_IPV4` and `LINKTYPE_IPV6`.
+    RawIp,
+}
+
+impl LinkType {
+    fn from_network(network: u32) -> Result<Self, Error> {
+        match network {
+            1 => Ok(LinkType::Ethernet),
+            9 => Ok(LinkType::Ppp),
+            12 | 14 | 101 | 228 | 229 => Ok(LinkType::RawIp),
+            _ => Err(Error::NotImplement(format!(
+                "Unsupported pcap link type: {}.",
+                network
+            ))),
+        }
+    }
+}
+
+/// A record of a pcap file: the capture time, the original length of the frame and the bytes
+/// that were captured, which may be fewer.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub time: Duration,
+    pub orig_len: u32,
+    pub data: Vec<u8>,
+}
+
+/// # PcapReader
+///
+/// Iterator over the records of a classic libpcap file, in either byte order and with
+/// microsecond or nanosecond timestamps.
+///
+/// A record cut short at the end of the file, as left by a simulation that was killed, ends
+/// the iteration without error. A record longer than the snapshot length of the file, or than
+/// 256 KiB, is a `ParseFail`.
+#[derive(Debug)]
+pub struct PcapReader<R> {
+    reader: R,
+    big_endian: bool,
+    nanos: bool,
+    snaplen: u32,
+    link_type: LinkType,
+}
+
+impl PcapReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0; 24];
+        reader.read_exact(&mut header)?;
+        let (big_endian, nanos) = match header[..4] {
+            [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+            [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+            [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+            [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+            [0x0a, 0x0d, 0x0d, 0x0a] => {
+                return Err(Error::NotImplement(
+                    "pcapng files are not supported.".to_string(),
+                ))
+            }
+            _ => return Err(Error::ParseFail("Not a pcap file.".to_string())),
+        };
+        let snaplen = read_u32(&header[16..], big_endian);
+        let network = read_u32(&header[20..], big_endian);
+        Ok(PcapReader {
+            reader,
+            big_endian,
+            nanos,
+            snaplen,
+            link_type: LinkType::from_network(network)?,
+        })
+    }
+
+    pub fn link_type(&self) -> LinkType {
+        self.link_type
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>, Error> {
+        let mut header = [0; 16];
+        if !read_full(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let secs = read_u32(&header[0..], self.big_endian) as u64;
+        let frac = read_u32(&header[4..], self.big_endian);
+        let incl_len = read_u32(&header[8..], self.big_endian);
+        let orig_len = read_u32(&header[12..], self.big_endian);
+        let time = match self.nanos {
+            true => Duration::new(secs, frac),
+            false => Duration::new(secs, 0) + Duration::from_micros(frac as u64),
+        };
+        // Some writers leave the snapshot length at 0.
+        let max_len = match self.snaplen {
+            0 => MAX_RECORD_LEN,
+            snaplen => snaplen.min(MAX_RECORD_LEN),
+        };
+        if incl_len > max_len {
+            return Err(Error::ParseFail(format!(
+                "Pcap record of {} bytes, longer than the limit of {} bytes.",
+                incl_len, max_len
+            )));
+        }
+        let mut data = vec![0; incl_len as usize];
+        if !read_full(&mut self.reader, &mut data)? {
+            return Ok(None);
+        }
+        Ok(Some(Record {
+            time,
+            orig_len,
+            data,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    match big_endian {
+        true => u32::from_be_bytes(bytes),
+        false => u32::from_le_bytes(bytes),
+    }
+}
+
+/// Fill `buf`, returning `false` if the end of the file comes first.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool, Error> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// TCP fields of a packet used to estimate RTTs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TcpSegment {
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub payload_len: u32,
+}
+
+impl TcpSegment {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const ACK: u8 = 0x10;
+
+    /// Sequence space taken by the segment, SYN and FIN counting for one.
+    fn seq_len(&self) -> u32 {
+        self.payload_len
+            + (self.flags & Self::SYN != 0) as u32
+            + (self.flags & Self::FIN != 0) as u32
+    }
+}
+
+/// An IP packet decoded from a record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Packet {
+    pub time: Duration,
+    pub five_tuple: FiveTuple,
+    /// Length of the IP packet, headers included, as announced by its header.
+    pub ip_len: u32,
+    pub tcp: Option<TcpSegment>,
+}
+
+/// Decode the IP packet carried by `record`. `None` is returned for frames that do not carry
+/// IPv4 or IPv6, such as ARP, and for packets truncated before the end of their headers.
+pub fn decode(link_type: LinkType, record: &Record) -> Option<Packet> {
+    let data = &record.data;
+    let (ethertype, ip) = match link_type {
+        LinkType::Ethernet => {
+            let mut ethertype = be16(data, 12)?;
+            let mut offset = 14;
+            if ethertype == 0x8100 {
+                ethertype = be16(data, 16)?;
+                offset = 18;
+            }
+            // 802.3 length followed by LLC/SNAP, as written by CSMA devices in LLC mode.
+            if ethertype <= 1500 && data.get(offset..offset + 3)? == [0xaa, 0xaa, 0x03] {
+                ethertype = be16(data, offset + 6)?;
+                offset += 8;
+            }
+            (ethertype, data.get(offset..)?)
+        }
+        LinkType::Ppp => {
+            let ethertype = match be16(data, 0)? {
+                0x0021 => 0x0800,
+                0x0057 => 0x86dd,
+                _ => return None,
+            };
+            (ethertype, data.get(2..)?)
+        }
+        LinkType::RawIp => match data.first()? >> 4 {
+            4 => (0x0800, &data[..]),
+            6 => (0x86dd, &data[..]),
+            _ => return None,
+        },
+    };
+    // IPv6 extension headers are not followed, the next header is taken as the transport.
+    let (source, destination, protocol, ip_len, ip_header_len) = match ethertype {
+        0x0800 => {
+            let source: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            (
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                *ip.get(9)?,
+                be16(ip, 2)? as u32,
+                (ip.first()? & 0x0f) as u32 * 4,
+            )
+        }
+        0x86dd => {
+            let source: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            (
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                *ip.get(6)?,
+                be16(ip, 4)? as u32 + 40,
+                40,
+            )
+        }
+        _ => return None,
+    };
+    let transport = ip.get(ip_header_len as usize..)?;
+    let transport_len = ip_len.saturating_sub(ip_header_len);
+    let (source_port, destination_port, tcp) = match protocol {
+        6 => {
+            let data_offset = (transport.get(12)? >> 4) as u32 * 4;
+            let tcp = TcpSegment {
+                seq: be32(transport, 4)?,
+                ack: be32(transport, 8)?,
+                flags: *transport.get(13)?,
+                payload_len: transport_len.saturating_sub(data_offset),
+            };
+            (be16(transport, 0)?, be16(transport, 2)?, Some(tcp))
+        }
+        17 => (be16(transport, 0)?, be16(transport, 2)?, None),
+        _ => (0, 0, None),
+    };
+    Some(Packet {
+        time: record.time,
+        five_tuple: FiveTuple {
+            source,
+            destination,
+            protocol,
+            source_port,
+            destination_port,
+        },
+        ip_len,
+        tcp,
+    })
+}
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Packets and IP bytes of a flow during one bin of a time series.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesPoint {
+    pub start: Duration,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RttSample {
+    /// Time of the acknowledgment.
+    pub time: Duration,
+    pub rtt: Duration,
+}
+
+/// # FlowSummary
+///
+/// What a pcap file shows of a flow in one direction.
+///
+/// RTT samples are taken on the data direction of TCP flows, by matching each segment with the
+/// first acknowledgment covering it, and skipping retransmitted segments. They measure the time
+/// from the capture point to the receiver and back, so they match the RTT seen by the sender
+/// when the capture is taken on the sender side.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowSummary {
+    pub five_tuple: FiveTuple,
+    pub packets: u64,
+    pub bytes: u64,
+    pub first: Duration,
+    pub last: Duration,
+    /// Width of the bins of `series`.
+    pub bin: Duration,
+    /// Time series from the bin of the first packet to the bin of the last one, empty bins
+    /// included.
+    pub series: Vec<SeriesPoint>,
+    pub rtt_samples: Vec<RttSample>,
+}
+
+impl FlowSummary {
+    /// IP bits per second between the first and the last packet.
+    pub fn throughput(&self) -> Option<f64> {
+        let duration = (self.last - self.first).as_secs_f64();
+        (duration > 0.0).then(|| self.bytes as f64 * 8.0 / duration)
+    }
+
+    /// IP bits per second of each bin of `series`.
+    pub fn throughput_series(&self) -> Vec<(Duration, f64)> {
+        let bin = self.bin.as_secs_f64();
+        self.series
+            .iter()
+            .map(|p| (p.start, p.bytes as f64 * 8.0 / bin))
+            .collect()
+    }
+
+    pub fn mean_rtt(&self) -> Option<Duration> {
+        let n = self.rtt_samples.len();
+        (n > 0).then(|| self.rtt_samples.iter().map(|s| s.rtt).sum::<Duration>() / n as u32)
+    }
+
+    pub fn min_rtt(&self) -> Option<Duration> {
+        self.rtt_samples.iter().map(|s| s.rtt).min()
+    }
+}
+
+/// Summary of a flow under construction.
+struct FlowState {
+    summary: FlowSummary,
+    /// Initial sequence number, to turn sequence numbers into offsets.
+    isn: Option<u32>,
+    /// End offset of each unacknowledged segment, with its time and whether it was sent more
+    /// than once.
+    unacked: BTreeMap<u64, (Duration, bool)>,
+}
+
+/// Summarize `packets` per flow, in order of first appearance, with time series of width
+/// `bin`.
+///
+/// Sequence numbers are taken relatively to the first segment seen, so RTT samples are only
+/// reliable for the first 4 GiB of each TCP flow.
+pub fn summarize(packets: impl IntoIterator<Item = Packet>, bin: Duration) -> Vec<FlowSummary> {
+    let bin = bin.max(Duration::from_nanos(1));
+    let mut flows: IndexMap<FiveTuple, FlowState> = IndexMap::new();
+    for packet in packets {
+        let flow = flows
+            .entry(packet.five_tuple.clone())
+            .or_insert_with(|| FlowState {
+                summary: FlowSummary {
+                    five_tuple: packet.five_tuple.clone(),
+                    packets: 0,
+                    bytes: 0,
+                    first: packet.time,
+                    last: packet.time,
+                    bin,
+                    series: vec![],
+                    rtt_samples: vec![],
+                },
+                isn: None,
+                unacked: BTreeMap::new(),
+            });
+        let summary = &mut flow.summary;
+        summary.packets += 1;
+        summary.bytes += packet.ip_len as u64;
+        summary.last = summary.last.max(packet.time);
+        let first_index = summary.first.as_nanos() / bin.as_nanos();
+        let position = (packet.time.as_nanos() / bin.as_nanos()).saturating_sub(first_index);
+        while summary.series.len() as u128 <= position {
+            let start = (first_index + summary.series.len() as u128) * bin.as_nanos();
+            summary.series.push(SeriesPoint {
+                start: Duration::from_nanos(start as u64),
+                packets: 0,
+                bytes: 0,
+            });
+        }
+        let point = &mut summary.series[position as usize];
+        point.packets += 1;
+        point.bytes += packet.ip_len as u64;
+        let Some(tcp) = packet.tcp else {
+            continue;
+        };
+        if tcp.seq_len() > 0 {
+            let isn = *flow.isn.get_or_insert(tcp.seq);
+            let end = tcp.seq.wrapping_sub(isn) as u64 + tcp.seq_len() as u64;
+            flow.unacked
+                .entry(end)
+                .and_modify(|(_, retransmitted)| *retransmitted = true)
+                .or_insert((packet.time, false));
+        }
+        if tcp.flags & TcpSegment::ACK != 0 {
+            if let Some(reverse) = flows.get_mut(&packet.five_tuple.reverse()) {
+                acknowledge(reverse, tcp.ack, packet.time);
+            }
+        }
+    }
+    flows.into_values().map(|f| f.summary).collect()
+}
+
+/// Drop the segments of `flow` covered by `ack`, and take an RTT sample from the last of them
+/// unless it was retransmitted.
+fn acknowledge(flow: &mut FlowState, ack: u32, time: Duration) {
+    let Some(isn) = flow.isn else {
+        return;
+    };
+    let acked = ack.wrapping_sub(isn) as u64;
+    let remaining = flow.unacked.split_off(&(acked + 1));
+    let covered = std::mem::replace(&mut flow.unacked, remaining);
+    if let Some((_, (sent, false))) = covered.into_iter().next_back() {
+        flow.summary.rtt_samples.push(RttSample {
+            time,
+            rtt: time.saturating_sub(sent),
+        });
+    }
+}
+
+/// Decode and summarize every IP packet of the pcap file at `path`, see `summarize`.
+pub fn summarize_file(path: impl AsRef<Path>, bin: Duration) -> Result<Vec<FlowSummary>, Error> {
+    let reader = PcapReader::open(path)?;
+    let link_type = reader.link_type();
+    let mut packets = vec![];
+    for record in reader {
+        if let Some(packet) = decode(link_type, &record?) {
+            packets.push(packet);
+        }
+    }
+    Ok(summarize(packets, bin))
+}
+
+/// Pcap files directly in `dir`, sorted by name.
+pub fn find(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    find_by_extension(dir.as_ref(), "pcap")
+}
+
+/// Summarize every pcap file in the working directory of a task, by file name.
+///
+/// Files are summarized separately: each is the view of one device, and merging them would
+/// count a packet once per device it went through.
+pub fn from_task<P: BuildCmd, O>(
+    task: &Task<P, O>,
+    bin: Duration,
+) -> Result<IndexMap<String, Vec<FlowSummary>>, Error> {
+    parse_each(find(&task.work_dir)?, |path| summarize_file(path, bin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header of a little-endian microsecond pcap file with `snaplen` and the PPP link type.
+    fn file_header(snaplen: u32) -> Vec<u8> {
+        let mut header = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        header.extend([0; 8]);
+        header.extend(snaplen.to_le_bytes());
+        header.extend(9u32.to_le_bytes());
+        header
+    }
+
+    fn record(micros: u64, data: &[u8]) -> Vec<u8> {
+        let mut record = vec![];
+        record.extend(((micros / 1_000_000) as u32).to_le_bytes());
+        record.extend(((micros % 1_000_000) as u32).to_le_bytes());
+        record.extend((data.len() as u32).to_le_bytes());
+        record.extend((data.len() as u32).to_le_bytes());
+        record.extend(data);
+        record
+    }
+
+    /// A PPP frame of an IPv4 TCP segment from 10.1.1.`from` to 10.1.1.`to`, the host with the
+    /// lower address being the client on port 49153.
+    fn tcp_frame(from: u8, to: u8, seq: u32, ack: u32, flags: u8, payload: u16) -> Vec<u8> {
+        let mut frame = vec![0x00, 0x21];
+        frame.extend([0x45, 0]);
+        frame.extend((40 + payload).to_be_bytes());
+        frame.extend([0, 0, 0, 0, 64, 6, 0, 0]);
+        frame.extend([10, 1, 1, from, 10, 1, 1, to]);
+        let (source_port, destination_port) = if from < to {
+            (49153u16, 80u16)
+        } else {
+            (80, 49153)
+        };
+        frame.extend(source_port.to_be_bytes());
+        frame.extend(destination_port.to_be_bytes());
+        frame.extend(seq.to_be_bytes());
+        frame.extend(ack.to_be_bytes());
+        frame.extend([0x50, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        frame.extend(vec![0; payload as usize]);
+        frame
+    }
+
+    fn packets(file: &[u8]) -> Vec<Packet> {
+        let reader = PcapReader::new(file).unwrap();
+        let link_type = reader.link_type();
+        reader
+            .map(|r| decode(link_type, &r.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn tcp_flows_are_summarized_with_rtt_samples() {
+        let mut file = file_header(65535);
+        file.extend(record(
+            1_000_000,
+            &tcp_frame(1, 2, 100, 0, TcpSegment::ACK, 500),
+        ));
+        file.extend(record(
+            1_020_000,
+            &tcp_frame(2, 1, 0, 600, TcpSegment::ACK, 0),
+        ));
+        let packets = packets(&file);
+        assert_eq!(packets[0].ip_len, 540);
+        assert_eq!(packets[0].five_tuple.destination_port, 80);
+        assert_eq!(packets[0].tcp.unwrap().payload_len, 500);
+        let flows = summarize(packets, Duration::from_millis(10));
+        assert_eq!(flows.len(), 2);
+        assert_eq!((flows[0].packets, flows[0].bytes), (1, 540));
+        assert_eq!(flows[0].min_rtt(), Some(Duration::from_millis(20)));
+        assert!(flows[1].rtt_samples.is_empty());
+    }
+
+    #[test]
+    fn records_longer_than_the_snapshot_length_are_rejected() {
+        let mut file = file_header(64);
+        file.extend(record(0, &[0; 65]));
+        let mut reader = PcapReader::new(&file[..]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(Error::ParseFail(_)))));
+
+        let mut file = file_header(0);
+        file.extend(0u32.to_le_bytes());
+        file.extend(0u32.to_le_bytes());
+        file.extend(u32::MAX.to_le_bytes());
+        file.extend(u32::MAX.to_le_bytes());
+        let mut reader = PcapReader::new(&file[..]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(Error::ParseFail(_)))));
+    }
+
+    #[test]
+    fn truncated_and_invalid_files() {
+        let mut file = file_header(65535);
+        file.extend(record(0, &tcp_frame(1, 2, 1, 0, TcpSegment::SYN, 0)));
+        let cut = file.len() - 10;
+        assert_eq!(PcapReader::new(&file[..cut]).unwrap().count(), 0);
+        assert_eq!(PcapReader::new(&file[..]).unwrap().count(), 1);
+        let mut pcapng = vec![0x0a, 0x0d, 0x0d, 0x0a];
+        pcapng.resize(28, 0);
+        assert!(matches!(
+            PcapReader::new(&pcapng[..]),
+            Err(Error::NotImplement(msg)) if msg == "pcapng files are not supported."
+        ));
+        assert!(matches!(
+            PcapReader::new(&[0; 24][..]),
+            Err(Error::ParseFail(_))
+        ));
+        let arp = [0xff; 20];
+        let record = Record {
+            time: Duration::ZERO,
+            orig_len: 20,
+            data: arp.to_vec(),
+        };
+        assert_eq!(decode(LinkType::Ethernet, &record), None);
+    }
+}
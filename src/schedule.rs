@@ -0,0 +1,262 @@
+//! Resource-aware scheduling of tasks
+//!
+//! Tasks are admitted in launch order while the resources they need fit in what is left:
+//! `task_concurrent` CPU slots, and a memory budget that defaults to the memory available when
+//! the campaign starts, `MemAvailable` in `/proc/meminfo`. A param states what its task needs
+//! with `BuildCmd::build_resources`, one CPU slot and no memory by default.
+//!
+//! With `ExecutorBuilder::learn_memory`, a task that does not state its memory is assumed to
+//! need as much as the largest peak resident set size observed so far among the finished tasks
+//! of its experiment, see `Task::peak_rss`. Tasks admitted before any task of their experiment
+//! finished are counted as needing no memory.
+//!
+//! A param may also state how long its task is expected to run, which `LaunchOrder::LongestFirst`
+//! orders tasks by, see `Executor::estimate_from` to learn it from earlier campaigns instead.
+//!
+//! The next task in launch order waits until it fits, later tasks do not overtake it, so a large
+//! task is never starved by smaller ones. A task needing more than the whole budget runs alone.
+//!
+//! ## Example
+//!
+//! ```
+//! use ns3_parallel::schedule::ResourceEstimate;
+//!
+//! use std::time::Duration;
+//!
+//! let estimate = ResourceEstimate::new()
+//!     .memory(4 << 30)
+//!     .cpus(2)
+//!     .runtime(Duration::from_secs(3 * 3600));
+//! assert_eq!(estimate.memory, Some(4 << 30));
+//! assert_eq!(ResourceEstimate::default().cpus, 1);
+//! assert_eq!(ResourceEstimate::new().cpus(0).cpus, 1);
+//! ```
+
+use futures::stream::{self, FuturesUnordered, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::task::Poll;
+use std::time::Duration;
+
+/// # ResourceEstimate
+///
+/// Resources the task of a param needs while it runs.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceEstimate {
+    /// Peak memory, in bytes. `None` when unknown.
+    pub memory: Option<u64>,
+    /// CPU slots, out of `task_concurrent`. Every task holds at least one, 0 counts as 1.
+    pub cpus: usize,
+    /// Wall-clock time the task is expected to run. `None` when unknown.
+    pub runtime: Option<Duration>,
+}
+
+impl Default for ResourceEstimate {
+    fn default() -> Self {
+        ResourceEstimate {
+            memory: None,
+            cpus: 1,
+            runtime: None,
+        }
+    }
+}
+
+impl ResourceEstimate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn memory(mut self, bytes: u64) -> Self {
+        self.memory = Some(bytes);
+        self
+    }
+
+    /// CPU slots the task needs, at least 1.
+    pub fn cpus(mut self, cpus: usize) -> Self {
+        self.cpus = cpus.max(1);
+        self
+    }
+
+    pub fn runtime(mut self, runtime: Duration) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+}
+
+/// Memory available for new processes without swapping, from `MemAvailable` in
+/// `/proc/meminfo`, in bytes.
+pub fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Resources held by running jobs, or needed by one.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Demand {
+    pub cpus: usize,
+    pub memory: u64,
+}
+
+/// Run `jobs` in order, admitting each one when the demand returned by `demand` for it fits in
+/// `capacity` along with the jobs already running, and yield their outputs as they finish. A job
+/// demanding no CPU slot holds one anyway.
+///
+/// `demand` is called when the job is about to be admitted, so it can use what was learned from
+/// the jobs that finished before.
+pub(crate) fn schedule<K, F: Future>(
+    jobs: Vec<(K, F)>,
+    capacity: Demand,
+    demand: impl Fn(&K) -> Demand,
+) -> impl Stream<Item = F::Output> {
+    let mut queue: VecDeque<(K, F)> = jobs.into();
+    let mut running = FuturesUnordered::new();
+    let mut used = Demand::default();
+    stream::poll_fn(move |cx| {
+        while let Some((key, _)) = queue.front() {
+            let mut needed = demand(key);
+            needed.cpus = needed.cpus.max(1);
+            let fits = used.cpus + needed.cpus <= capacity.cpus
+                && used.memory.saturating_add(needed.memory) <= capacity.memory;
+            if !fits && !running.is_empty() {
+                break;
+            }
+            let (_, job) = queue.pop_front().unwrap();
+            used.cpus += needed.cpus;
+            used.memory = used.memory.saturating_add(needed.memory);
+            running.push(async move { (needed, job.await) });
+        }
+        match running.poll_next_unpin(cx) {
+            Poll::Ready(Some((needed, output))) => {
+                used.cpus -= needed.cpus;
+                used.memory = used.memory.saturating_sub(needed.memory);
+                Poll::Ready(Some(output))
+            }
+            // Nothing runs only once the queue is empty, a job is always admitted otherwise.
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Usage {
+        memory: u64,
+        running: usize,
+        /// Job, memory in use and jobs running once it started.
+        started: Vec<(usize, u64, usize)>,
+    }
+
+    #[tokio::test]
+    async fn jobs_are_admitted_in_order_within_the_memory_budget() {
+        let memory = [6, 6, 3, 12, 1];
+        let usage = Arc::new(Mutex::new(Usage::default()));
+        let jobs = memory
+            .iter()
+            .enumerate()
+            .map(|(id, &needed)| {
+                let usage = usage.clone();
+                let job = async move {
+                    {
+                        let mut usage = usage.lock().unwrap();
+                        usage.memory += needed;
+                        usage.running += 1;
+                        let started = (id, usage.memory, usage.running);
+                        usage.started.push(started);
+                    }
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    let mut usage = usage.lock().unwrap();
+                    usage.memory -= needed;
+                    usage.running -= 1;
+                    id
+                };
+                (id, job)
+            })
+            .collect();
+        let capacity = Demand {
+            cpus: 4,
+            memory: 10,
+        };
+        let finished: Vec<usize> = schedule(jobs, capacity, |&id| Demand {
+            cpus: 1,
+            memory: memory[id],
+        })
+        .collect()
+        .await;
+
+        assert_eq!(finished.len(), memory.len());
+        let started = &usage.lock().unwrap().started;
+        // The second job waits for the first one, and the third one does not overtake it.
+        let order: Vec<usize> = started.iter().map(|&(id, _, _)| id).collect();
+        assert_eq!(order, [0, 1, 2, 3, 4]);
+        assert_eq!(started[1], (1, 6, 1));
+        assert_eq!(started[2], (2, 9, 2));
+        // A job over the whole budget runs alone.
+        assert_eq!(started[3], (3, 12, 1));
+        assert_eq!(started[4], (4, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn cpu_slots_bound_the_running_jobs() {
+        let running = Arc::new(Mutex::new((0, 0)));
+        let jobs = (0..6)
+            .map(|id| {
+                let running = running.clone();
+                let job = async move {
+                    {
+                        let mut running = running.lock().unwrap();
+                        running.0 += 1;
+                        running.1 = running.1.max(running.0);
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    running.lock().unwrap().0 -= 1;
+                };
+                (id, job)
+            })
+            .collect();
+        let capacity = Demand {
+            cpus: 4,
+            memory: u64::MAX,
+        };
+        let demand = |&id: &usize| Demand {
+            cpus: if id == 0 { 3 } else { 1 },
+            memory: 0,
+        };
+        assert_eq!(schedule(jobs, capacity, demand).count().await, 6);
+        assert_eq!(running.lock().unwrap().1, 4);
+    }
+
+    #[tokio::test]
+    async fn jobs_without_cpu_slots_hold_one() {
+        let running = Arc::new(Mutex::new((0, 0)));
+        let jobs = (0..6)
+            .map(|id| {
+                let running = running.clone();
+                let job = async move {
+                    {
+                        let mut running = running.lock().unwrap();
+                        running.0 += 1;
+                        running.1 = running.1.max(running.0);
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    running.lock().unwrap().0 -= 1;
+                };
+                (id, job)
+            })
+            .collect();
+        let capacity = Demand {
+            cpus: 2,
+            memory: u64::MAX,
+        };
+        let demand = |_: &usize| Demand { cpus: 0, memory: 0 };
+        assert_eq!(schedule(jobs, capacity, demand).count().await, 6);
+        assert_eq!(running.lock().unwrap().1, 2);
+    }
+}
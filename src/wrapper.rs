@@ -0,0 +1,143 @@
+//! Debugger and profiler wrappers
+//!
+//! A `Wrapper` runs the ns-3 program of a task under a tool such as valgrind or gdb, through
+//! waf's `--command-template`, and has the tool write its report in the directory of the task,
+//! which requires `ExecutorBuilder::output_dir`. The reports found once the task finished are
+//! listed in `Task::reports`.
+//!
+//! Wrappers are set for every experiment with `ExecutorBuilder::wrapper`, for one experiment
+//! with its reserved `wrapper` key in the config file, or only for the retries of crashed tasks
+//! with `ExecutorBuilder::crash_wrapper`:
+//!
+//! ```toml
+//! [exp-1]
+//! policy = [1, 2, 3]
+//! wrapper = "valgrind"
+//!
+//! [exp-2]
+//! policy = [4]
+//! wrapper = { custom = { template = "strace -f -o {report} %s", report = "strace.log" } }
+//! ```
+//!
+//! ## Example
+//!
+//! ```
+//! use ns3_parallel::wrapper::Wrapper;
+//! use std::path::Path;
+//!
+//! let template = Wrapper::Valgrind.template(Path::new("/out/exp-1/0"));
+//! assert_eq!(
+//!     template,
+//!     "valgrind --leak-check=full --log-file=/out/exp-1/0/valgrind.log %s"
+//! );
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::plan::shell_quote;
+
+/// # Wrapper
+///
+/// Tool the ns-3 program runs under, and the report it writes in the directory of the task.
+///
+/// - `Valgrind`: memcheck with leak checking, report in `valgrind.log`.
+/// - `Perf`: `perf record` with call graphs, profile in `perf.data`.
+/// - `Gdb`: run in batch mode and print a backtrace when the program stops, log in `gdb.log`.
+/// - `Heaptrack`: heap profile in `heaptrack.*`, the extension depending on the compression.
+/// - `Custom`: `template`, in which `%s` stands for the program and its arguments and
+///   `{report}` for the path of the report, named `report` in the directory of the task.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Wrapper {
+    Valgrind,
+    Perf,
+    Gdb,
+    Heaptrack,
+    Custom { template: String, report: String },
+}
+
+impl Wrapper {
+    /// Name of the report in the directory of the task.
+    pub fn report_name(&self) -> &str {
+        match self {
+            Wrapper::Valgrind => "valgrind.log",
+            Wrapper::Perf => "perf.data",
+            Wrapper::Gdb => "gdb.log",
+            Wrapper::Heaptrack => "heaptrack",
+            Wrapper::Custom { report, .. } => report,
+        }
+    }
+
+    /// Command template for waf, writing the report in `work_dir`. `%` in the path of the report
+    /// is escaped from waf's formatting.
+    pub fn template(&self, work_dir: &Path) -> String {
+        let report = work_dir
+            .join(self.report_name())
+            .display()
+            .to_string()
+            .replace('%', "%%");
+        match self {
+            Wrapper::Valgrind => format!(
+                "valgrind --leak-check=full --log-file={} %s",
+                shell_quote(&report)
+            ),
+            Wrapper::Perf => format!("perf record -g -o {} %s", shell_quote(&report)),
+            // gdb exits with the status of the program, or 1 when it was stopped by a signal.
+            Wrapper::Gdb => format!(
+                "gdb -batch -ex {} -ex 'set logging enabled on' -ex run -ex bt \
+                 -ex 'quit $_isvoid($_exitcode) ? 1 : $_exitcode' --args %s",
+                shell_quote(&format!("set logging file {}", report))
+            ),
+            Wrapper::Heaptrack => format!("heaptrack -o {} %s", shell_quote(&report)),
+            Wrapper::Custom { template, .. } => template.replace("{report}", &shell_quote(&report)),
+        }
+    }
+
+    /// Reports written in `work_dir`, possibly several when the tool adds an extension or a
+    /// suffix to the name it is given.
+    pub fn find_reports(&self, work_dir: &Path) -> Vec<PathBuf> {
+        let name = self.report_name();
+        let Ok(entries) = std::fs::read_dir(work_dir) else {
+            return vec![];
+        };
+        let mut reports: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(name))
+            .map(|entry| entry.path())
+            .collect();
+        reports.sort();
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gdb_logs_to_the_report() {
+        assert_eq!(
+            Wrapper::Gdb.template(Path::new("/out/exp-1/0")),
+            "gdb -batch -ex 'set logging file /out/exp-1/0/gdb.log' -ex 'set logging enabled on' \
+             -ex run -ex bt -ex 'quit $_isvoid($_exitcode) ? 1 : $_exitcode' --args %s"
+        );
+    }
+
+    #[test]
+    fn percent_signs_in_the_report_path_are_escaped() {
+        let work_dir = Path::new("/out/load 50%/0");
+        assert_eq!(
+            Wrapper::Perf.template(work_dir),
+            "perf record -g -o '/out/load 50%%/0/perf.data' %s"
+        );
+        let custom = Wrapper::Custom {
+            template: "strace -o {report} %s".to_string(),
+            report: "strace.log".to_string(),
+        };
+        assert_eq!(
+            custom.template(work_dir),
+            "strace -o '/out/load 50%%/0/strace.log' %s"
+        );
+    }
+}